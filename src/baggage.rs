@@ -0,0 +1,170 @@
+use opentelemetry::api;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Header used by the W3C Baggage format.
+const BAGGAGE_HEADER: &str = "baggage";
+
+/// Key-value pairs propagated alongside the trace, such as a tenant id.
+///
+/// Baggage is set on spans with [`OpenTelemetrySpanExt::set_baggage`] and
+/// inherited by the child spans created afterwards. Cloning baggage is cheap,
+/// the entries are only copied when a clone is modified.
+///
+/// [`OpenTelemetrySpanExt::set_baggage`]: trait.OpenTelemetrySpanExt.html#tymethod.set_baggage
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Baggage {
+    entries: Arc<HashMap<String, String>>,
+}
+
+impl Baggage {
+    /// Create empty baggage.
+    pub fn new() -> Self {
+        Baggage::default()
+    }
+
+    /// Returns the value of the entry with the given key.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries.get(key).map(String::as_str)
+    }
+
+    /// Set the value of the entry with the given key, returning the previous
+    /// value.
+    pub fn insert<K, V>(&mut self, key: K, value: V) -> Option<String>
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        Arc::make_mut(&mut self.entries).insert(key.into(), value.into())
+    }
+
+    /// Remove the entry with the given key, returning its value.
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        if !self.entries.contains_key(key) {
+            return None;
+        }
+        Arc::make_mut(&mut self.entries).remove(key)
+    }
+
+    /// Returns an iterator over the keys and values of all entries.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    /// Returns the number of entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if there are no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Propagates [`Baggage`] in the W3C Baggage `baggage` header, next to the
+/// span context propagated with an `api::HttpTextFormat`.
+///
+/// ```rust
+/// use opentelemetry::api::{self, HttpTextFormat};
+/// use std::collections::HashMap;
+/// use tracing_opentelemetry::{BaggagePropagator, OpenTelemetrySpanExt};
+///
+/// let carrier = HashMap::new();
+/// let span = tracing::info_span!("request");
+///
+/// // Extract the parent context and baggage of an incoming request
+/// span.set_parent(api::TraceContextPropagator::new().extract(&carrier));
+/// for (key, value) in BaggagePropagator::new().extract(&carrier).iter() {
+///     span.set_baggage(key, value);
+/// }
+///
+/// // Inject the context and baggage of the span into an outgoing request
+/// let mut outgoing = HashMap::new();
+/// api::TraceContextPropagator::new().inject(span.context(), &mut outgoing);
+/// BaggagePropagator::new().inject(&span.baggage(), &mut outgoing);
+/// ```
+///
+/// [`Baggage`]: struct.Baggage.html
+#[derive(Clone, Debug, Default)]
+pub struct BaggagePropagator {
+    _private: (),
+}
+
+impl BaggagePropagator {
+    /// Create a new W3C Baggage propagator.
+    pub fn new() -> Self {
+        BaggagePropagator::default()
+    }
+
+    /// Encode the given baggage into the carrier. Nothing is injected for
+    /// empty baggage.
+    pub fn inject(&self, baggage: &Baggage, carrier: &mut dyn api::Carrier) {
+        if baggage.is_empty() {
+            return;
+        }
+
+        let header = baggage
+            .iter()
+            .map(|(key, value)| format!("{}={}", encode(key), encode(value)))
+            .collect::<Vec<_>>()
+            .join(",");
+        carrier.set(BAGGAGE_HEADER, header);
+    }
+
+    /// Decode the baggage in the carrier. Invalid entries are skipped and
+    /// entry properties are ignored.
+    pub fn extract(&self, carrier: &dyn api::Carrier) -> Baggage {
+        let mut baggage = Baggage::new();
+        let header = match carrier.get(BAGGAGE_HEADER) {
+            Some(header) => header,
+            None => return baggage,
+        };
+
+        for member in header.split(',') {
+            let entry = member.split(';').next().unwrap_or_default();
+            let mut parts = entry.splitn(2, '=');
+            let key = parts.next().map(str::trim).and_then(decode);
+            let value = parts.next().map(str::trim).and_then(decode);
+            if let (Some(key), Some(value)) = (key, value) {
+                if !key.is_empty() {
+                    baggage.insert(key, value);
+                }
+            }
+        }
+
+        baggage
+    }
+}
+
+/// Percent-encode all bytes which are not allowed unencoded in baggage.
+fn encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'%' | b'"' | b',' | b';' | b'\\' => encoded.push_str(&format!("%{:02X}", byte)),
+            0x21..=0x7E => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Decode a percent-encoded value, or `None` if it is malformed.
+fn decode(value: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut rest = value.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
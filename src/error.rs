@@ -0,0 +1,36 @@
+use std::fmt;
+use tracing_core::span;
+
+/// An internal error the layer recovered from by skipping the affected
+/// operation.
+///
+/// Errors are reported to the handler configured with
+/// [`OpenTelemetryLayerBuilder::with_error_handler`].
+///
+/// [`OpenTelemetryLayerBuilder::with_error_handler`]: struct.OpenTelemetryLayerBuilder.html#method.with_error_handler
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum Error {
+    /// No span with the given id was found in the subscriber's registry.
+    SpanNotFound(span::Id),
+    /// The current dispatcher does not downcast to the subscriber type the
+    /// layer was built for, e.g. because the layer was added to a different
+    /// subscriber than expected.
+    SubscriberMismatch,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::SpanNotFound(id) => write!(f, "span {:?} not found in the registry", id),
+            Error::SubscriberMismatch => {
+                write!(
+                    f,
+                    "dispatcher does not downcast to the layer's subscriber type"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
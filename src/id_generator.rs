@@ -0,0 +1,47 @@
+use opentelemetry::api;
+
+/// Interface for generating the OpenTelemetry `TraceId` and `SpanId` of new
+/// spans.
+///
+/// ```rust
+/// use opentelemetry::api;
+/// use std::sync::atomic::{AtomicU64, Ordering};
+/// use tracing_opentelemetry::IdGenerator;
+///
+/// /// Generates sequential ids, e.g. for deterministic tests.
+/// #[derive(Default)]
+/// struct SequentialIdGenerator(AtomicU64);
+///
+/// impl IdGenerator for SequentialIdGenerator {
+///     fn new_trace_id(&self) -> api::TraceId {
+///         api::TraceId::from_u128(self.0.fetch_add(1, Ordering::Relaxed) as u128 + 1)
+///     }
+///
+///     fn new_span_id(&self) -> api::SpanId {
+///         api::SpanId::from_u64(self.0.fetch_add(1, Ordering::Relaxed) + 1)
+///     }
+/// }
+/// ```
+pub trait IdGenerator: Send + Sync {
+    /// Generate a new `TraceId` for a span without a parent.
+    fn new_trace_id(&self) -> api::TraceId;
+
+    /// Generate a new `SpanId`.
+    fn new_span_id(&self) -> api::SpanId;
+}
+
+/// The default `IdGenerator`, which generates random ids.
+#[derive(Clone, Debug, Default)]
+pub struct RandomIdGenerator {
+    _private: (),
+}
+
+impl IdGenerator for RandomIdGenerator {
+    fn new_trace_id(&self) -> api::TraceId {
+        api::TraceId::from_u128(rand::random())
+    }
+
+    fn new_span_id(&self) -> api::SpanId {
+        api::SpanId::from_u64(rand::random())
+    }
+}
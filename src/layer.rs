@@ -1,51 +1,506 @@
+use crate::tracer::{self, TracerRouter, Tracers};
+use crate::{Baggage, Error, IdGenerator, PreSampledTracer, RandomIdGenerator};
 use opentelemetry::api;
 use std::any::TypeId;
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::collections::{HashMap, VecDeque};
+use std::error;
 use std::fmt;
 use std::marker;
-use std::time::SystemTime;
+use std::sync::{Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 use tracing_core::span::{self, Attributes, Id, Record};
-use tracing_core::{field, Event, Subscriber};
+use tracing_core::{field, Event, Metadata, Subscriber};
+use tracing_subscriber::filter::LevelFilter;
 use tracing_subscriber::layer::Context;
-use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::registry::{LookupSpan, SpanRef};
 use tracing_subscriber::Layer;
 
+const SPAN_NAME_FIELD: &str = "otel.name";
+const SPAN_KIND_FIELD: &str = "otel.kind";
+const SPAN_STATUS_CODE_FIELD: &str = "otel.status_code";
+const SPAN_STATUS_MESSAGE_FIELD: &str = "otel.status_message";
+const EXCEPTION_TYPE_FIELD: &str = "exception.type";
+const RESERVED_FIELDS: [&str; 4] = [
+    SPAN_NAME_FIELD,
+    SPAN_KIND_FIELD,
+    SPAN_STATUS_CODE_FIELD,
+    SPAN_STATUS_MESSAGE_FIELD,
+];
+
+/// Filter on the target of events recorded as OpenTelemetry span events.
+type TargetFilter = Box<dyn Fn(&str) -> bool + Send + Sync>;
+type ErrorHandler = Box<dyn Fn(Error) + Send + Sync>;
+/// Makes the sampling decision for a span with the given tracer, if the
+/// tracer's sampler is available.
+type PreSample<T> = fn(&T, &api::SpanBuilder) -> Option<api::SamplingResult>;
+/// Makes the sampling decision for a span with the tracer for its target.
+pub(crate) type Sampler<'a> = dyn Fn(&api::SpanBuilder) -> Option<api::SamplingResult> + 'a;
+
+thread_local! {
+    /// Set while an orphan event span is exported on this thread. Events the
+    /// exporter emits outside of spans are then dropped, as exporting them
+    /// would recurse without limit.
+    // `const` initializers need Rust 1.59.
+    #[allow(clippy::missing_const_for_thread_local)]
+    static EXPORTING_ORPHAN: Cell<bool> = Cell::new(false);
+}
+
+/// Marks the current thread as exporting an orphan event span until dropped,
+/// including while unwinding from a panic in the exporter.
+struct ExportingOrphan(bool);
+
+impl ExportingOrphan {
+    fn enter() -> Self {
+        ExportingOrphan(EXPORTING_ORPHAN.with(|exporting| exporting.replace(true)))
+    }
+}
+
+impl Drop for ExportingOrphan {
+    fn drop(&mut self) {
+        EXPORTING_ORPHAN.with(|exporting| exporting.set(self.0));
+    }
+}
+
 /// OpenTelemetry layer for use in a project that uses [tracing].
 ///
 /// [tracing]: https://github.com/tokio-rs/tracing
 pub struct OpenTelemetryLayer<S, T: api::Tracer> {
-    tracer: T,
+    tracers: Tracers<T>,
+    pre_sample: PreSample<T>,
+    id_generator: Box<dyn IdGenerator>,
+    tracked_inactivity: bool,
+    location: bool,
+    threads: bool,
+    orphan_events: OrphanEvents,
+    event_level: LevelFilter,
+    event_target_filter: Option<TargetFilter>,
+    limits: SpanLimits,
+    error_handler: ErrorHandler,
+    baggage_attributes: Vec<String>,
+    orphan_spans: Mutex<HashMap<thread::ThreadId, OtelData>>,
 
     get_context: WithContext,
     _registry: marker::PhantomData<S>,
 }
 
+/// Configures how events that are not in the context of any span are
+/// recorded.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OrphanEvents {
+    /// Drop events outside of spans. This is the default.
+    Drop,
+    /// Export each event outside of spans as its own zero-duration span.
+    Span,
+    /// Record events outside of spans in an `orphan_events` span per thread.
+    /// The span is exported once it is older than the given interval when the
+    /// next such event is recorded, when [`OpenTelemetryLayer::flush`] is
+    /// called, or when the layer is dropped.
+    ///
+    /// [`OpenTelemetryLayer::flush`]: struct.OpenTelemetryLayer.html#method.flush
+    ThreadSpan(Duration),
+}
+
+/// Configures which events are kept once a span has reached its maximum
+/// number of events.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EventRetention {
+    /// Keep the first events recorded in the span. This is the default.
+    First,
+    /// Keep the last events recorded in the span.
+    Last,
+}
+
+/// Limits on the data recorded in each span.
+#[derive(Clone, Debug)]
+pub(crate) struct SpanLimits {
+    max_events: Option<usize>,
+    event_retention: EventRetention,
+    max_attributes: Option<usize>,
+    max_attribute_value_length: Option<usize>,
+}
+
+impl Default for SpanLimits {
+    fn default() -> Self {
+        SpanLimits {
+            max_events: None,
+            event_retention: EventRetention::First,
+            max_attributes: None,
+            max_attribute_value_length: None,
+        }
+    }
+}
+
+impl SpanLimits {
+    /// Truncate string and byte values longer than the configured maximum.
+    fn truncate(&self, value: &mut api::Value) {
+        let max = match self.max_attribute_value_length {
+            Some(max) => max,
+            None => return,
+        };
+
+        match value {
+            api::Value::String(value) if value.len() > max => {
+                let mut len = max;
+                while !value.is_char_boundary(len) {
+                    len -= 1;
+                }
+                value.truncate(len);
+            }
+            api::Value::Bytes(value) => value.truncate(max),
+            _ => {}
+        }
+    }
+}
+
+thread_local! {
+    /// Set while the layer handles a callback on this thread, during which it
+    /// may hold the extensions lock of a span.
+    // `const` initializers need Rust 1.59.
+    #[allow(clippy::missing_const_for_thread_local)]
+    static IN_LAYER: Cell<bool> = Cell::new(false);
+}
+
+/// Marks the current thread as inside the layer until dropped, including
+/// while unwinding from a panic in the layer.
+struct InLayer(bool);
+
+impl InLayer {
+    fn enter() -> Self {
+        InLayer(IN_LAYER.with(|in_layer| in_layer.replace(true)))
+    }
+}
+
+impl Drop for InLayer {
+    fn drop(&mut self) {
+        IN_LAYER.with(|in_layer| in_layer.set(self.0));
+    }
+}
+
+/// Returns `true` if the layer is handling a callback on this thread, so
+/// that span data must not be accessed as it may already be locked.
+pub(crate) fn in_layer() -> bool {
+    IN_LAYER.with(Cell::get)
+}
+
+impl<S, T: api::Tracer> OpenTelemetryLayer<S, T> {
+    /// Export the per-thread orphan event spans recorded so far, see
+    /// [`OrphanEvents::ThreadSpan`].
+    ///
+    /// A layer installed as the global default is never dropped, so this
+    /// should be called before the process exits to export the remaining
+    /// events:
+    ///
+    /// ```rust
+    /// use opentelemetry::sdk;
+    /// use tracing_opentelemetry::OpenTelemetryLayer;
+    /// use tracing_subscriber::Registry;
+    ///
+    /// tracing::dispatcher::get_default(|dispatch| {
+    ///     if let Some(layer) = dispatch.downcast_ref::<OpenTelemetryLayer<Registry, sdk::Tracer>>() {
+    ///         layer.flush();
+    ///     }
+    /// });
+    /// ```
+    ///
+    /// [`OrphanEvents::ThreadSpan`]: enum.OrphanEvents.html#variant.ThreadSpan
+    pub fn flush(&self) {
+        let orphan_spans: Vec<OtelData> = self
+            .orphan_spans
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .drain()
+            .map(|(_, data)| data)
+            .collect();
+        self.export_orphan_spans(orphan_spans, SystemTime::now());
+    }
+
+    /// Export orphan event spans, dropping the events the exporter emits
+    /// meanwhile.
+    fn export_orphan_spans(&self, orphan_spans: Vec<OtelData>, end_time: SystemTime) {
+        let _exporting = ExportingOrphan::enter();
+        for data in orphan_spans {
+            let tracer = self.tracers.get(data.target);
+            data.export(&*tracer, end_time);
+        }
+    }
+}
+
+impl<S, T: api::Tracer> Drop for OpenTelemetryLayer<S, T> {
+    /// Export any remaining per-thread orphan event spans.
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
 // this function "remembers" the types of the subscriber so that we
 // can downcast to something aware of them without knowing those
 // types at the callsite.
 //
 // See https://github.com/tokio-rs/tracing/blob/4dad420ee1d4607bad79270c1520673fa6266a3d/tracing-error/src/layer.rs
+#[allow(clippy::type_complexity)]
 pub(crate) struct WithContext(
-    fn(&tracing::Dispatch, &span::Id, f: &mut dyn FnMut(&mut api::SpanBuilder)),
+    fn(&tracing::Dispatch, &span::Id, f: &mut dyn FnMut(&mut OtelData, &Sampler<'_>, &SpanLimits)),
 );
 
 impl WithContext {
     // This function allows a function to be called in the context of the
     // "remembered" subscriber.
-    pub(crate) fn with_context<'a>(
+    pub(crate) fn with_context(
         &self,
-        dispatch: &'a tracing::Dispatch,
+        dispatch: &tracing::Dispatch,
         id: &span::Id,
-        mut f: impl FnMut(&mut api::SpanBuilder),
+        mut f: impl FnMut(&mut OtelData, &Sampler<'_>, &SpanLimits),
     ) {
         (self.0)(dispatch, id, &mut f)
     }
 }
 
-pub(crate) fn build_context(builder: &mut api::SpanBuilder) -> api::SpanContext {
+/// OpenTelemetry data tracked in the extensions of each `tracing` span.
+pub(crate) struct OtelData {
+    /// The builder used to create the OpenTelemetry span when it closes.
+    pub(crate) builder: api::SpanBuilder,
+    /// The sampling decision for spans which start a local trace, either
+    /// without a parent or with a remote parent. Other spans inherit the
+    /// decision of their parent.
+    pub(crate) sampling_result: Option<api::SamplingResult>,
+    /// The target of the `tracing` span, which selects its tracer.
+    target: &'static str,
+    /// Whether the parent context was taken from the parent `tracing` span.
+    /// It is then resolved again from the ancestors of the span, as their
+    /// trace may change with a later `set_parent`.
+    local_parent: bool,
+    /// Baggage of the span, inherited from its parent when it was created.
+    pub(crate) baggage: Baggage,
+    /// Span events, kept apart from the builder so that the most recent
+    /// events can be retained efficiently.
+    events: VecDeque<api::Event>,
+    /// Position of each attribute key in the builder's attributes, so that
+    /// re-recorded fields replace their previous value.
+    attribute_index: HashMap<api::Key, usize>,
+    dropped_events: u64,
+    dropped_attributes: u64,
+}
+
+impl OtelData {
+    fn new(builder: api::SpanBuilder, target: &'static str) -> Self {
+        OtelData {
+            builder,
+            sampling_result: None,
+            target,
+            local_parent: false,
+            baggage: Baggage::new(),
+            events: VecDeque::new(),
+            attribute_index: HashMap::new(),
+            dropped_events: 0,
+            dropped_attributes: 0,
+        }
+    }
+
+    /// Add an attribute to the span within the given `limits`, replacing any
+    /// previous value with the same key.
+    pub(crate) fn push_attribute(&mut self, mut attribute: api::KeyValue, limits: &SpanLimits) {
+        limits.truncate(&mut attribute.value);
+
+        let attributes = self.builder.attributes.get_or_insert_with(Vec::new);
+        if let Some(&index) = self.attribute_index.get(&attribute.key) {
+            attributes[index] = attribute;
+            return;
+        }
+
+        if let Some(max) = limits.max_attributes {
+            if attributes.len() >= max {
+                self.dropped_attributes += 1;
+                return;
+            }
+        }
+
+        self.attribute_index
+            .insert(attribute.key.clone(), attributes.len());
+        attributes.push(attribute);
+    }
+
+    /// Record an attribute set through a span field or
+    /// `OpenTelemetrySpanExt::set_attribute`. The reserved
+    /// `otel.name`, `otel.kind`, `otel.status_code` and `otel.status_message`
+    /// fields configure the span instead.
+    pub(crate) fn record_attribute(&mut self, attribute: api::KeyValue, limits: &SpanLimits) {
+        let name: Cow<'static, str> = attribute.key.clone().into();
+        match (name.as_ref(), attribute.value) {
+            (SPAN_NAME_FIELD, api::Value::String(name)) => self.builder.name = name,
+            (SPAN_KIND_FIELD, api::Value::String(kind)) => {
+                // Unknown kinds leave the span kind unchanged.
+                if let Some(span_kind) = str_to_span_kind(&kind) {
+                    self.builder.span_kind = Some(span_kind);
+                }
+            }
+            (SPAN_STATUS_CODE_FIELD, api::Value::String(code)) => {
+                self.set_status_code(str_to_status_code(&code))
+            }
+            (SPAN_STATUS_CODE_FIELD, api::Value::I64(code)) if code >= 0 => {
+                self.set_status_code(u64_to_status_code(code as u64))
+            }
+            (SPAN_STATUS_CODE_FIELD, api::Value::U64(code)) => {
+                self.set_status_code(u64_to_status_code(code))
+            }
+            (SPAN_STATUS_MESSAGE_FIELD, api::Value::String(message)) => {
+                self.builder.status_message = Some(message)
+            }
+            // Reserved fields whose value could not configure the span, e.g. a
+            // negative status code, are dropped rather than exported.
+            (name, _) if RESERVED_FIELDS.contains(&name) => {}
+            (_, value) => self.push_attribute(api::KeyValue::new(attribute.key, value), limits),
+        }
+    }
+
+    /// Record the span fields collected by a `SpanAttributeVisitor`.
+    fn record_fields(&mut self, fields: SpanAttributeVisitor, limits: &SpanLimits) {
+        for attribute in fields.0 {
+            self.record_attribute(attribute, limits);
+        }
+        for exception in fields.1.into_events() {
+            self.record_exception(exception, limits);
+        }
+    }
+
+    /// Set the span status, leaving it unchanged for unknown status codes.
+    fn set_status_code(&mut self, status_code: Option<api::StatusCode>) {
+        if status_code.is_some() {
+            self.builder.status_code = status_code;
+        }
+    }
+
+    /// Add a link to another span.
+    pub(crate) fn push_link(&mut self, link: api::Link) {
+        self.builder.links.get_or_insert_with(Vec::new).push(link);
+    }
+
+    /// Add an event to the span within the given `limits`.
+    pub(crate) fn push_event(&mut self, mut event: api::Event, limits: &SpanLimits) {
+        if let Some(max) = limits.max_events {
+            if self.events.len() >= max {
+                self.dropped_events += 1;
+                match limits.event_retention {
+                    EventRetention::Last if max > 0 => {
+                        self.events.pop_front();
+                    }
+                    _ => return,
+                }
+            }
+        }
+
+        for attribute in &mut event.attributes {
+            limits.truncate(&mut attribute.value);
+        }
+        self.events.push_back(event);
+    }
+
+    /// Add an `exception` event to the span and mark it as failed, unless a
+    /// status was already set.
+    pub(crate) fn record_exception(&mut self, exception: api::Event, limits: &SpanLimits) {
+        if self.builder.status_code.is_none() {
+            self.builder.status_code = Some(api::StatusCode::Unknown);
+        }
+        self.push_event(exception, limits);
+    }
+
+    /// Build and export the OpenTelemetry span, unless the sampling decision
+    /// was not to record it.
+    fn export<T: api::Tracer>(mut self, tracer: &T, end_time: SystemTime) {
+        if !self.is_recording() {
+            return;
+        }
+
+        // The tracer samples spans with a remote parent again, so they are
+        // built with a local parent context carrying the decision made when
+        // the span was created instead.
+        if let Some(sampling_result) = self.sampling_result.take() {
+            if let Some(parent_context) = self.builder.parent_context.take() {
+                let trace_flags = match sampling_result.decision {
+                    api::SamplingDecision::RecordAndSampled => api::TRACE_FLAG_SAMPLED,
+                    _ => 0,
+                };
+                self.builder.parent_context = Some(api::SpanContext::new(
+                    parent_context.trace_id(),
+                    parent_context.span_id(),
+                    trace_flags,
+                    false,
+                ));
+                self.builder
+                    .attributes
+                    .get_or_insert_with(Vec::new)
+                    .extend(sampling_result.attributes);
+            }
+        }
+
+        if !self.events.is_empty() {
+            self.builder
+                .message_events
+                .get_or_insert_with(Vec::new)
+                .extend(self.events);
+        }
+
+        if self.dropped_events > 0 || self.dropped_attributes > 0 {
+            let attributes = self.builder.attributes.get_or_insert_with(Vec::new);
+            if self.dropped_events > 0 {
+                attributes
+                    .push(api::Key::new("otel.dropped_events_count").u64(self.dropped_events));
+            }
+            if self.dropped_attributes > 0 {
+                attributes.push(
+                    api::Key::new("otel.dropped_attributes_count").u64(self.dropped_attributes),
+                );
+            }
+        }
+
+        // Assign end time, build and start span, drop span to export
+        self.builder.with_end_time(end_time).start(tracer);
+    }
+
+    /// Replace the parent context of the span, e.g. with a remote context
+    /// extracted from a request, and sample the span again.
+    pub(crate) fn set_parent(&mut self, parent_context: api::SpanContext, sampler: &Sampler<'_>) {
+        self.builder.parent_context = Some(parent_context);
+        self.local_parent = false;
+        self.sample(sampler);
+    }
+
+    /// Make the sampling decision for this span with the given `sampler`.
+    /// Without a decision, the span is assumed to be sampled if it has no
+    /// parent and inherits the flags of a remote parent.
+    pub(crate) fn sample(&mut self, sampler: &Sampler<'_>) {
+        self.sampling_result = match &self.builder.parent_context {
+            Some(parent_context) if !parent_context.is_remote() => None,
+            _ => sampler(&self.builder),
+        };
+    }
+
+    /// Returns `false` if the sampling decision was not to record this span.
+    fn is_recording(&self) -> bool {
+        !matches!(
+            self.sampling_result,
+            Some(api::SamplingResult {
+                decision: api::SamplingDecision::NotRecord,
+                ..
+            })
+        )
+    }
+}
+
+pub(crate) fn build_context(data: &OtelData) -> api::SpanContext {
+    context_with_parent(data, data.builder.parent_context.as_ref())
+}
+
+/// Build the context of the span as if it had the given parent context.
+fn context_with_parent(
+    data: &OtelData,
+    parent_context: Option<&api::SpanContext>,
+) -> api::SpanContext {
+    let builder = &data.builder;
     let span_id = builder.span_id.expect("Builders must have id");
-    let (trace_id, trace_flags) = builder
-        .parent_context
-        .as_ref()
+    let (trace_id, trace_flags) = parent_context
         .map(|parent_context| (parent_context.trace_id(), parent_context.trace_flags()))
         .unwrap_or_else(|| {
             (
@@ -54,36 +509,311 @@ pub(crate) fn build_context(builder: &mut api::SpanBuilder) -> api::SpanContext
             )
         });
 
+    // Apply the local sampling decision, if one was made for this span.
+    let trace_flags = match data.sampling_result {
+        Some(api::SamplingResult {
+            decision: api::SamplingDecision::RecordAndSampled,
+            ..
+        }) => trace_flags | api::TRACE_FLAG_SAMPLED,
+        Some(_) => trace_flags & !api::TRACE_FLAG_SAMPLED,
+        None => trace_flags,
+    };
+
+    // The span itself is always local, a remote parent is kept as given in
+    // the builder's `parent_context`.
     api::SpanContext::new(trace_id, span_id, trace_flags, false)
 }
 
-struct SpanEventVisitor<'a>(&'a mut api::Event);
+/// Time spent inside and outside of a span, tracked when the layer is
+/// configured with `with_tracked_inactivity`.
+struct Timings {
+    idle: u64,
+    busy: u64,
+    last: Instant,
+}
+
+impl Timings {
+    fn new() -> Self {
+        Timings {
+            idle: 0,
+            busy: 0,
+            last: Instant::now(),
+        }
+    }
+}
+
+/// The source code location of the given callsite as semantic convention
+/// `code.*` attributes.
+fn location_attributes(metadata: &Metadata<'_>) -> Vec<api::KeyValue> {
+    let mut attributes = Vec::with_capacity(3);
+    if let Some(filepath) = metadata.file() {
+        attributes.push(api::Key::new("code.filepath").string(filepath));
+    }
+    if let Some(lineno) = metadata.line() {
+        attributes.push(api::Key::new("code.lineno").i64(lineno as i64));
+    }
+    if let Some(namespace) = metadata.module_path() {
+        attributes.push(api::Key::new("code.namespace").string(namespace));
+    }
+    attributes
+}
+
+/// The id and name of the current thread as `thread.id` and `thread.name`
+/// attributes.
+fn thread_attributes() -> Vec<api::KeyValue> {
+    let mut attributes = Vec::with_capacity(2);
+    let current = thread::current();
+    if let Some(id) = thread_id_integer(current.id()) {
+        attributes.push(api::Key::new("thread.id").u64(id));
+    }
+    if let Some(name) = current.name() {
+        attributes.push(api::Key::new("thread.name").string(name));
+    }
+    attributes
+}
+
+/// `ThreadId::as_u64` is unstable, so the integer id is parsed from the
+/// `Debug` representation instead, e.g. `ThreadId(1)`.
+fn thread_id_integer(id: thread::ThreadId) -> Option<u64> {
+    let id = format!("{:?}", id);
+    id.trim_start_matches("ThreadId(")
+        .trim_end_matches(')')
+        .parse()
+        .ok()
+}
+
+fn str_to_span_kind(s: &str) -> Option<api::SpanKind> {
+    if s.eq_ignore_ascii_case("server") {
+        Some(api::SpanKind::Server)
+    } else if s.eq_ignore_ascii_case("client") {
+        Some(api::SpanKind::Client)
+    } else if s.eq_ignore_ascii_case("producer") {
+        Some(api::SpanKind::Producer)
+    } else if s.eq_ignore_ascii_case("consumer") {
+        Some(api::SpanKind::Consumer)
+    } else if s.eq_ignore_ascii_case("internal") {
+        Some(api::SpanKind::Internal)
+    } else {
+        None
+    }
+}
+
+fn str_to_status_code(s: &str) -> Option<api::StatusCode> {
+    // Accept both `InvalidArgument` and `INVALID_ARGUMENT` style names.
+    let normalized: String = s
+        .chars()
+        .filter(|c| *c != '_')
+        .flat_map(char::to_lowercase)
+        .collect();
+
+    match normalized.as_str() {
+        "ok" => Some(api::StatusCode::OK),
+        "canceled" | "cancelled" => Some(api::StatusCode::Canceled),
+        "unknown" => Some(api::StatusCode::Unknown),
+        "invalidargument" => Some(api::StatusCode::InvalidArgument),
+        "deadlineexceeded" => Some(api::StatusCode::DeadlineExceeded),
+        "notfound" => Some(api::StatusCode::NotFound),
+        "alreadyexists" => Some(api::StatusCode::AlreadyExists),
+        "permissiondenied" => Some(api::StatusCode::PermissionDenied),
+        "resourceexhausted" => Some(api::StatusCode::ResourceExhausted),
+        "failedprecondition" => Some(api::StatusCode::FailedPrecondition),
+        "aborted" => Some(api::StatusCode::Aborted),
+        "outofrange" => Some(api::StatusCode::OutOfRange),
+        "unimplemented" => Some(api::StatusCode::Unimplemented),
+        "internal" => Some(api::StatusCode::Internal),
+        "unavailable" => Some(api::StatusCode::Unavailable),
+        "dataloss" => Some(api::StatusCode::DataLoss),
+        "unauthenticated" => Some(api::StatusCode::Unauthenticated),
+        _ => None,
+    }
+}
+
+fn u64_to_status_code(code: u64) -> Option<api::StatusCode> {
+    match code {
+        0 => Some(api::StatusCode::OK),
+        1 => Some(api::StatusCode::Canceled),
+        2 => Some(api::StatusCode::Unknown),
+        3 => Some(api::StatusCode::InvalidArgument),
+        4 => Some(api::StatusCode::DeadlineExceeded),
+        5 => Some(api::StatusCode::NotFound),
+        6 => Some(api::StatusCode::AlreadyExists),
+        7 => Some(api::StatusCode::PermissionDenied),
+        8 => Some(api::StatusCode::ResourceExhausted),
+        9 => Some(api::StatusCode::FailedPrecondition),
+        10 => Some(api::StatusCode::Aborted),
+        11 => Some(api::StatusCode::OutOfRange),
+        12 => Some(api::StatusCode::Unimplemented),
+        13 => Some(api::StatusCode::Internal),
+        14 => Some(api::StatusCode::Unavailable),
+        15 => Some(api::StatusCode::DataLoss),
+        16 => Some(api::StatusCode::Unauthenticated),
+        _ => None,
+    }
+}
+
+/// Build an `exception` event following the OpenTelemetry semantic
+/// conventions from the given error, with its chain of sources as the
+/// `exception.stacktrace`.
+fn exception_event(error: &(dyn error::Error + 'static)) -> api::Event {
+    let mut attributes = vec![api::Key::new("exception.message").string(error.to_string())];
+
+    let mut chain = Vec::new();
+    let mut source = error.source();
+    while let Some(error) = source {
+        chain.push(error.to_string());
+        source = error.source();
+    }
+    if !chain.is_empty() {
+        attributes.push(api::Key::new("exception.stacktrace").string(chain.join("\n")));
+    }
+
+    api::Event::new("exception".to_string(), SystemTime::now(), attributes)
+}
+
+/// `exception` events of the errors recorded in one set of fields.
+///
+/// The concrete type of an error is not available through `dyn Error`, so
+/// it is taken from an `exception.type` field recorded along with the error
+/// instead of being guessed.
+#[derive(Default)]
+struct Exceptions {
+    events: Vec<api::Event>,
+    exception_type: Option<String>,
+}
+
+impl Exceptions {
+    /// Returns the `exception` events with the recorded `exception.type`.
+    fn into_events(self) -> Vec<api::Event> {
+        let mut events = self.events;
+        if let Some(exception_type) = self.exception_type {
+            for event in &mut events {
+                event
+                    .attributes
+                    .push(api::Key::new(EXCEPTION_TYPE_FIELD).string(exception_type.clone()));
+            }
+        }
+        events
+    }
+}
+
+/// A tracing event converted into an OpenTelemetry span event.
+struct ConvertedEvent {
+    event: api::Event,
+    /// `exception` events of the errors recorded in the event.
+    exceptions: Vec<api::Event>,
+    /// Whether the event has the `ERROR` level, which marks the span as failed.
+    error: bool,
+}
+
+/// Records event fields as attributes of the OpenTelemetry event, collecting
+/// errors as separate `exception` events.
+struct SpanEventVisitor<'a>(&'a mut api::Event, Exceptions);
 
 impl<'a> field::Visit for SpanEventVisitor<'a> {
-    /// Record events on the underlying OpenTelemetry `Span`.
+    /// Record events on the underlying OpenTelemetry `Span` from `bool` values.
+    fn record_bool(&mut self, field: &field::Field, value: bool) {
+        self.0
+            .attributes
+            .push(api::Key::new(field.name()).bool(value));
+    }
+
+    /// Record events on the underlying OpenTelemetry `Span` from `i64` values.
+    fn record_i64(&mut self, field: &field::Field, value: i64) {
+        self.0
+            .attributes
+            .push(api::Key::new(field.name()).i64(value));
+    }
+
+    /// Record events on the underlying OpenTelemetry `Span` from `u64` values.
+    fn record_u64(&mut self, field: &field::Field, value: u64) {
+        self.0
+            .attributes
+            .push(api::Key::new(field.name()).u64(value));
+    }
+
+    /// Record events on the underlying OpenTelemetry `Span` from `&str` values.
+    fn record_str(&mut self, field: &field::Field, value: &str) {
+        match field.name() {
+            "message" => self.0.name = value.to_string(),
+            EXCEPTION_TYPE_FIELD => self.1.exception_type = Some(value.to_string()),
+            _ => self
+                .0
+                .attributes
+                .push(api::Key::new(field.name()).string(value)),
+        }
+    }
+
+    /// Record events on the underlying OpenTelemetry `Span` from values that
+    /// implement Debug.
     fn record_debug(&mut self, field: &field::Field, value: &dyn fmt::Debug) {
-        if field.name() == "message" {
-            self.0.name = format!("{:?}", value);
-        } else {
-            self.0
+        match field.name() {
+            "message" => self.0.name = format!("{:?}", value),
+            EXCEPTION_TYPE_FIELD => self.1.exception_type = Some(format!("{:?}", value)),
+            _ => self
+                .0
                 .attributes
-                .push(api::Key::new(field.name()).string(format!("{:?}", value)));
+                .push(api::Key::new(field.name()).string(format!("{:?}", value))),
         }
     }
+
+    /// Record events on the underlying OpenTelemetry `Span` from values that
+    /// implement `Error`, adding an `exception` event for the error.
+    fn record_error(&mut self, field: &field::Field, value: &(dyn error::Error + 'static)) {
+        self.0
+            .attributes
+            .push(api::Key::new(field.name()).string(value.to_string()));
+        self.1.events.push(exception_event(value));
+    }
 }
 
-struct SpanAttributeVisitor<'a>(&'a mut api::SpanBuilder);
+/// Collects span fields as attributes, and errors as `exception` events.
+/// They are recorded in the span data afterwards, so that the span is not
+/// locked while user `Debug` and `Display` implementations run.
+#[derive(Default)]
+struct SpanAttributeVisitor(Vec<api::KeyValue>, Exceptions);
+
+impl field::Visit for SpanAttributeVisitor {
+    /// Set attributes on the underlying OpenTelemetry `Span` from `bool` values.
+    fn record_bool(&mut self, field: &field::Field, value: bool) {
+        self.0.push(api::Key::new(field.name()).bool(value));
+    }
+
+    /// Set attributes on the underlying OpenTelemetry `Span` from `i64` values.
+    fn record_i64(&mut self, field: &field::Field, value: i64) {
+        self.0.push(api::Key::new(field.name()).i64(value));
+    }
+
+    /// Set attributes on the underlying OpenTelemetry `Span` from `u64` values.
+    fn record_u64(&mut self, field: &field::Field, value: u64) {
+        self.0.push(api::Key::new(field.name()).u64(value));
+    }
+
+    /// Set attributes on the underlying OpenTelemetry `Span` from `&str` values.
+    fn record_str(&mut self, field: &field::Field, value: &str) {
+        match field.name() {
+            EXCEPTION_TYPE_FIELD => self.1.exception_type = Some(value.to_string()),
+            _ => self.0.push(api::Key::new(field.name()).string(value)),
+        }
+    }
 
-impl<'a> field::Visit for SpanAttributeVisitor<'a> {
-    /// Set attributes on the underlying OpenTelemetry `Span`.
+    /// Set attributes on the underlying OpenTelemetry `Span` from values that
+    /// implement Debug.
     fn record_debug(&mut self, field: &field::Field, value: &dyn fmt::Debug) {
-        let attribute = api::Key::new(field.name()).string(format!("{:?}", value));
-        if let Some(attributes) = &mut self.0.attributes {
-            attributes.push(attribute);
-        } else {
-            self.0.attributes = Some(vec![attribute]);
+        match field.name() {
+            EXCEPTION_TYPE_FIELD => self.1.exception_type = Some(format!("{:?}", value)),
+            _ => self
+                .0
+                .push(api::Key::new(field.name()).string(format!("{:?}", value))),
         }
     }
+
+    /// Set attributes on the underlying OpenTelemetry `Span` from values that
+    /// implement `Error`, adding an `exception` event for the error.
+    fn record_error(&mut self, field: &field::Field, value: &(dyn error::Error + 'static)) {
+        self.0
+            .push(api::Key::new(field.name()).string(value.to_string()));
+        self.1.events.push(exception_event(value));
+    }
 }
 
 impl<S, T> OpenTelemetryLayer<S, T>
@@ -91,32 +821,54 @@ where
     S: Subscriber + for<'span> LookupSpan<'span>,
     T: api::Tracer + 'static,
 {
-    /// Retrieve the parent OpenTelemetry [`SpanContext`] from the current
-    /// tracing [`span`] through the [`Registry`]. This [`SpanContext`]
-    /// links spans to their parent for proper hierarchical visualization.
+    /// Retrieve the parent OpenTelemetry [`SpanContext`] and the baggage to
+    /// inherit from the current tracing [`span`] through the [`Registry`].
+    /// This [`SpanContext`] links spans to their parent for proper
+    /// hierarchical visualization.
     fn parent_context(
         &self,
         attrs: &Attributes<'_>,
         ctx: &Context<'_, S>,
-    ) -> Option<api::SpanContext> {
+    ) -> Option<(api::SpanContext, Baggage)> {
+        let parent_data = |data: &OtelData| (build_context(data), data.baggage.clone());
+
         // If a span is specified, it _should_ exist in the underlying `Registry`.
         if let Some(parent) = attrs.parent() {
-            let span = ctx.span(parent).expect("Span not found, this is a bug");
-            let mut extensions = span.extensions_mut();
-            extensions.get_mut::<api::SpanBuilder>().map(build_context)
+            self.with_data(parent, ctx, parent_data)
         // Else if the span is inferred from context, look up any available current span.
         } else if attrs.is_contextual() {
-            ctx.current_span().id().and_then(|span_id| {
-                let span = ctx.span(span_id).expect("Span not found, this is a bug");
-                let mut extensions = span.extensions_mut();
-                extensions.get_mut::<api::SpanBuilder>().map(build_context)
-            })
+            ctx.current_span()
+                .id()
+                .and_then(|span_id| self.with_data(span_id, ctx, parent_data))
         // Explicit root spans should have no parent context.
         } else {
             None
         }
     }
 
+    /// Look up the span with the given id, reporting an error if it does not
+    /// exist.
+    fn span<'a>(&self, id: &span::Id, ctx: &'a Context<'_, S>) -> Option<SpanRef<'a, S>> {
+        let span = ctx.span(id);
+        if span.is_none() {
+            (self.error_handler)(Error::SpanNotFound(id.clone()));
+        }
+        span
+    }
+
+    /// Call `f` with the OpenTelemetry data of the tracing span with the
+    /// given id, if it has any.
+    fn with_data<R>(
+        &self,
+        id: &span::Id,
+        ctx: &Context<'_, S>,
+        f: impl FnOnce(&OtelData) -> R,
+    ) -> Option<R> {
+        let span = self.span(id, ctx)?;
+        let extensions = span.extensions();
+        extensions.get::<OtelData>().map(f)
+    }
+
     /// Set the `OpenTelemetry` `Tracer` that this layer will use to produce
     /// and track `Span`s.
     ///
@@ -155,29 +907,667 @@ where
     /// let _subscriber = Registry::default()
     ///     .with(otel_layer);
     /// ```
+    ///
+    /// This is a shortcut for building a layer with the default options, see
+    /// [`OpenTelemetryLayer::builder`] to configure the layer.
+    ///
+    /// The sampling decision of spans is made with [`PreSampledTracer`] when
+    /// they are created, for tracers whose sampler is available to the layer.
+    /// Spans of other tracers, such as a `global::BoxedTracer`, are treated as
+    /// sampled in the propagated context.
+    ///
+    /// [`OpenTelemetryLayer::builder`]: struct.OpenTelemetryLayer.html#method.builder
+    /// [`PreSampledTracer`]: trait.PreSampledTracer.html
     pub fn with_tracer(tracer: T) -> Self {
-        OpenTelemetryLayer {
-            tracer,
-            get_context: WithContext(Self::get_context),
-            _registry: marker::PhantomData,
+        OpenTelemetryLayer::builder(tracer).build()
+    }
+
+    /// Returns `true` if the event with the given metadata should be recorded
+    /// as an OpenTelemetry span event.
+    fn event_enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.level() <= &self.event_level
+            && match &self.event_target_filter {
+                Some(filter) => filter(metadata.target()),
+                None => true,
+            }
+    }
+
+    /// Create the data of a new root span, used to record orphan events.
+    fn root_data(&self, name: &str, target: &'static str, start_time: SystemTime) -> OtelData {
+        let tracer = self.tracers.get(target);
+        let builder = tracer
+            .span_builder(name)
+            .with_start_time(start_time)
+            .with_span_id(self.id_generator.new_span_id())
+            .with_trace_id(self.id_generator.new_trace_id());
+
+        let mut data = OtelData::new(builder, target);
+        data.sample(&|builder| (self.pre_sample)(&tracer, builder));
+        data
+    }
+
+    /// Export the span with the tracer for its target.
+    fn export(&self, data: OtelData, end_time: SystemTime) {
+        let tracer = self.tracers.get(data.target);
+        data.export(&*tracer, end_time);
+    }
+
+    /// Convert a tracing event into an OpenTelemetry span event. This runs
+    /// the `Debug` and `Display` implementations of the recorded fields, so it
+    /// is done before any span is locked.
+    fn convert_event(&self, event: &Event<'_>) -> ConvertedEvent {
+        let metadata = event.metadata();
+        let mut otel_event = api::Event::new(
+            String::new(),
+            SystemTime::now(),
+            vec![
+                api::Key::new("level").string(metadata.level().to_string()),
+                api::Key::new("target").string(metadata.target()),
+            ],
+        );
+
+        if self.location {
+            otel_event.attributes.extend(location_attributes(metadata));
+        }
+
+        if self.threads {
+            otel_event.attributes.extend(thread_attributes());
+        }
+
+        let mut visitor = SpanEventVisitor(&mut otel_event, Exceptions::default());
+        event.record(&mut visitor);
+        let exceptions = visitor.1.into_events();
+
+        ConvertedEvent {
+            event: otel_event,
+            exceptions,
+            error: *metadata.level() == tracing_core::Level::ERROR,
+        }
+    }
+
+    /// Record a converted event in the given span.
+    fn record_event(&self, data: &mut OtelData, event: ConvertedEvent) {
+        if event.error && data.builder.status_code.is_none() {
+            data.builder.status_code = Some(api::StatusCode::Unknown);
+        }
+
+        data.push_event(event.event, &self.limits);
+        for exception in event.exceptions {
+            data.record_exception(exception, &self.limits);
+        }
+    }
+
+    /// Record an event which is not in the context of any span according to
+    /// the configured `OrphanEvents`.
+    fn record_orphan_event(&self, event: &Event<'_>) {
+        match self.orphan_events {
+            OrphanEvents::Drop => {}
+            OrphanEvents::Span => {
+                if EXPORTING_ORPHAN.with(Cell::get) {
+                    return;
+                }
+
+                let now = SystemTime::now();
+                let metadata = event.metadata();
+                let otel_event = self.convert_event(event);
+                let mut data = self.root_data(metadata.name(), metadata.target(), now);
+                self.record_event(&mut data, otel_event);
+
+                // Name the span after the event message if it has one.
+                if let Some(otel_event) = data.events.front() {
+                    if !otel_event.name.is_empty() {
+                        data.builder.name = otel_event.name.clone();
+                    }
+                }
+
+                self.export_orphan_spans(vec![data], now);
+            }
+            OrphanEvents::ThreadSpan(interval) => {
+                if EXPORTING_ORPHAN.with(Cell::get) {
+                    return;
+                }
+
+                let now = SystemTime::now();
+                let otel_event = self.convert_event(event);
+                let mut orphan_spans = self
+                    .orphan_spans
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner);
+                let data = orphan_spans
+                    .entry(thread::current().id())
+                    .or_insert_with(|| {
+                        self.root_data("orphan_events", event.metadata().target(), now)
+                    });
+                self.record_event(data, otel_event);
+
+                // Export the spans of all threads which are due, without
+                // holding the lock while exporting.
+                let expired: Vec<thread::ThreadId> = orphan_spans
+                    .iter()
+                    .filter(|(_, data)| match data.builder.start_time {
+                        Some(start_time) => match now.duration_since(start_time) {
+                            Ok(age) => age >= interval,
+                            Err(_) => false,
+                        },
+                        None => false,
+                    })
+                    .map(|(thread_id, _)| *thread_id)
+                    .collect();
+                let expired = expired
+                    .iter()
+                    .filter_map(|thread_id| orphan_spans.remove(thread_id))
+                    .collect();
+                drop(orphan_spans);
+                self.export_orphan_spans(expired, now);
+            }
+        }
+    }
+
+    /// Resolve the parent context of a span again from its ancestors, which
+    /// may have been given a new parent with `set_parent` after the span was
+    /// created.
+    fn resolve_parent_context(span: &SpanRef<'_, S>, data: &mut OtelData) {
+        if !data.local_parent {
+            return;
+        }
+
+        if let Some(parent_context) = span
+            .parent()
+            .and_then(|parent| Self::resolve_context(&parent))
+        {
+            data.builder.parent_context = Some(parent_context);
+        }
+    }
+
+    /// Build the current context of a span, resolving its trace through its
+    /// ancestors.
+    fn resolve_context(span: &SpanRef<'_, S>) -> Option<api::SpanContext> {
+        let extensions = span.extensions();
+        let data = extensions.get::<OtelData>()?;
+        let parent_context = if data.local_parent {
+            span.parent()
+                .and_then(|parent| Self::resolve_context(&parent))
+        } else {
+            None
+        };
+
+        match parent_context {
+            Some(parent_context) => Some(context_with_parent(data, Some(&parent_context))),
+            None => Some(build_context(data)),
         }
     }
 
     fn get_context(
         dispatch: &tracing::Dispatch,
         id: &span::Id,
-        f: &mut dyn FnMut(&mut api::SpanBuilder),
+        f: &mut dyn FnMut(&mut OtelData, &Sampler<'_>, &SpanLimits),
     ) {
-        let subscriber = dispatch
-            .downcast_ref::<S>()
-            .expect("subscriber should downcast to expected type; this is a bug!");
-        let span = subscriber
-            .span(id)
-            .expect("registry should have a span for the current ID");
+        let _in_layer = InLayer::enter();
+        // Without the layer there is no error handler to report to.
+        let layer = match dispatch.downcast_ref::<OpenTelemetryLayer<S, T>>() {
+            Some(layer) => layer,
+            None => return,
+        };
+        let subscriber = match dispatch.downcast_ref::<S>() {
+            Some(subscriber) => subscriber,
+            None => return (layer.error_handler)(Error::SubscriberMismatch),
+        };
+        let span = match subscriber.span(id) {
+            Some(span) => span,
+            None => return (layer.error_handler)(Error::SpanNotFound(id.clone())),
+        };
 
         let mut extensions = span.extensions_mut();
-        if let Some(builder) = extensions.get_mut::<api::SpanBuilder>() {
-            f(builder);
+        if let Some(data) = extensions.get_mut::<OtelData>() {
+            Self::resolve_parent_context(&span, data);
+            let tracer = layer.tracers.get(data.target);
+            f(
+                data,
+                &|builder| (layer.pre_sample)(&tracer, builder),
+                &layer.limits,
+            );
+        }
+    }
+}
+
+impl<S, T: api::Tracer + 'static> OpenTelemetryLayer<S, T> {
+    /// Create a builder to configure an `OpenTelemetryLayer` which uses the
+    /// given `Tracer` to produce and track spans.
+    ///
+    /// Only the samplers of `sdk::Tracer`s are used when spans are created,
+    /// see [`OpenTelemetryLayer::with_tracer`] and
+    /// [`OpenTelemetryLayerBuilder::with_pre_sampling`].
+    ///
+    /// ```rust,no_run
+    /// use opentelemetry::{api, api::Provider, sdk};
+    /// use tracing_opentelemetry::{OpenTelemetryLayer, OrphanEvents};
+    /// use tracing_subscriber::filter::LevelFilter;
+    /// use tracing_subscriber::layer::SubscriberExt;
+    /// use tracing_subscriber::Registry;
+    ///
+    /// let tracer = sdk::Provider::default().get_tracer("component-name");
+    ///
+    /// let otel_layer = OpenTelemetryLayer::builder(tracer)
+    ///     .with_location(true)
+    ///     .with_event_level(LevelFilter::INFO)
+    ///     .with_orphan_events(OrphanEvents::Span)
+    ///     .with_max_events_per_span(128)
+    ///     .build();
+    ///
+    /// let _subscriber = Registry::default().with(otel_layer);
+    /// ```
+    ///
+    /// [`OpenTelemetryLayer::with_tracer`]: struct.OpenTelemetryLayer.html#method.with_tracer
+    /// [`OpenTelemetryLayerBuilder::with_pre_sampling`]: struct.OpenTelemetryLayerBuilder.html#method.with_pre_sampling
+    pub fn builder(tracer: T) -> OpenTelemetryLayerBuilder<S, T> {
+        OpenTelemetryLayerBuilder::new(Tracers::Single(tracer))
+    }
+
+    /// Create a builder to configure an `OpenTelemetryLayer` which routes
+    /// spans to tracers based on their target with the given
+    /// [`TracerRouter`].
+    ///
+    /// ```rust,no_run
+    /// use opentelemetry::sdk;
+    /// use tracing_opentelemetry::{OpenTelemetryLayer, TracerRouter};
+    /// use tracing_subscriber::layer::SubscriberExt;
+    /// use tracing_subscriber::Registry;
+    ///
+    /// let router = TracerRouter::new(sdk::Provider::default())
+    ///     .with_prefix("my_app::db", "database");
+    /// let otel_layer = OpenTelemetryLayer::routed_builder(router).build();
+    ///
+    /// let _subscriber = Registry::default().with(otel_layer);
+    /// ```
+    ///
+    /// [`TracerRouter`]: struct.TracerRouter.html
+    pub fn routed_builder(router: TracerRouter<T>) -> OpenTelemetryLayerBuilder<S, T> {
+        OpenTelemetryLayerBuilder::new(Tracers::Routed(router))
+    }
+}
+
+/// Builder for an [`OpenTelemetryLayer`], created with
+/// [`OpenTelemetryLayer::builder`] or [`OpenTelemetryLayer::routed_builder`].
+///
+/// [`OpenTelemetryLayer`]: struct.OpenTelemetryLayer.html
+/// [`OpenTelemetryLayer::builder`]: struct.OpenTelemetryLayer.html#method.builder
+/// [`OpenTelemetryLayer::routed_builder`]: struct.OpenTelemetryLayer.html#method.routed_builder
+pub struct OpenTelemetryLayerBuilder<S, T> {
+    tracers: Tracers<T>,
+    pre_sample: PreSample<T>,
+    id_generator: Box<dyn IdGenerator>,
+    tracked_inactivity: bool,
+    location: bool,
+    threads: bool,
+    orphan_events: OrphanEvents,
+    event_level: LevelFilter,
+    event_target_filter: Option<TargetFilter>,
+    limits: SpanLimits,
+    error_handler: ErrorHandler,
+    baggage_attributes: Vec<String>,
+    _registry: marker::PhantomData<S>,
+}
+
+impl<S, T: 'static> OpenTelemetryLayerBuilder<S, T> {
+    fn new(tracers: Tracers<T>) -> Self {
+        OpenTelemetryLayerBuilder {
+            tracers,
+            pre_sample: tracer::pre_sample,
+            id_generator: Box::new(RandomIdGenerator::default()),
+            tracked_inactivity: false,
+            location: false,
+            threads: false,
+            orphan_events: OrphanEvents::Drop,
+            event_level: LevelFilter::TRACE,
+            event_target_filter: None,
+            limits: SpanLimits::default(),
+            error_handler: Box::new(|error| eprintln!("[tracing-opentelemetry] {}", error)),
+            baggage_attributes: Vec::new(),
+            _registry: marker::PhantomData,
+        }
+    }
+}
+
+impl<S, T> OpenTelemetryLayerBuilder<S, T> {
+    /// Set the `IdGenerator` the layer will use to assign trace and span ids
+    /// to new spans. Defaults to a [`RandomIdGenerator`].
+    ///
+    /// ```rust,no_run
+    /// use opentelemetry::{api, api::Provider, sdk};
+    /// use tracing_opentelemetry::{OpenTelemetryLayer, IdGenerator};
+    /// use tracing_subscriber::Registry;
+    ///
+    /// /// Generates trace ids prefixed with the current unix time in seconds.
+    /// struct TimestampIdGenerator;
+    ///
+    /// impl IdGenerator for TimestampIdGenerator {
+    ///     fn new_trace_id(&self) -> api::TraceId {
+    ///         let secs = std::time::SystemTime::now()
+    ///             .duration_since(std::time::UNIX_EPOCH)
+    ///             .unwrap()
+    ///             .as_secs() as u128;
+    ///         let random = rand::random::<u128>() & ((1 << 96) - 1);
+    ///         api::TraceId::from_u128(secs << 96 | random)
+    ///     }
+    ///
+    ///     fn new_span_id(&self) -> api::SpanId {
+    ///         api::SpanId::from_u64(rand::random())
+    ///     }
+    /// }
+    ///
+    /// let tracer = sdk::Provider::default().get_tracer("component-name");
+    /// let otel_layer = OpenTelemetryLayer::<Registry, _>::builder(tracer)
+    ///     .with_id_generator(TimestampIdGenerator)
+    ///     .build();
+    /// ```
+    ///
+    /// [`RandomIdGenerator`]: struct.RandomIdGenerator.html
+    pub fn with_id_generator<G>(mut self, id_generator: G) -> Self
+    where
+        G: IdGenerator + 'static,
+    {
+        self.id_generator = Box::new(id_generator);
+        self
+    }
+
+    /// Sets whether or not spans record the time they were entered (`busy_ns`)
+    /// and not entered (`idle_ns`) as attributes. Defaults to `false`.
+    ///
+    /// This is most useful for spans of async tasks, which are entered and
+    /// exited each time the task is polled.
+    ///
+    /// ```rust,no_run
+    /// use opentelemetry::{api::Provider, sdk};
+    /// use tracing_opentelemetry::OpenTelemetryLayer;
+    /// use tracing_subscriber::Registry;
+    ///
+    /// let tracer = sdk::Provider::default().get_tracer("component-name");
+    /// let otel_layer = OpenTelemetryLayer::<Registry, _>::builder(tracer)
+    ///     .with_tracked_inactivity(true)
+    ///     .build();
+    /// ```
+    pub fn with_tracked_inactivity(mut self, tracked_inactivity: bool) -> Self {
+        self.tracked_inactivity = tracked_inactivity;
+        self
+    }
+
+    /// Sets whether or not spans and events record the file, line and module
+    /// of their source code location as `code.filepath`, `code.lineno` and
+    /// `code.namespace` attributes. Defaults to `false`.
+    ///
+    /// ```rust,no_run
+    /// use opentelemetry::{api::Provider, sdk};
+    /// use tracing_opentelemetry::OpenTelemetryLayer;
+    /// use tracing_subscriber::Registry;
+    ///
+    /// let tracer = sdk::Provider::default().get_tracer("component-name");
+    /// let otel_layer = OpenTelemetryLayer::<Registry, _>::builder(tracer)
+    ///     .with_location(true)
+    ///     .build();
+    /// ```
+    pub fn with_location(mut self, location: bool) -> Self {
+        self.location = location;
+        self
+    }
+
+    /// Sets whether or not spans and events record the id and name of the
+    /// thread they were created on as `thread.id` and `thread.name`
+    /// attributes. Defaults to `false`.
+    ///
+    /// ```rust,no_run
+    /// use opentelemetry::{api::Provider, sdk};
+    /// use tracing_opentelemetry::OpenTelemetryLayer;
+    /// use tracing_subscriber::Registry;
+    ///
+    /// let tracer = sdk::Provider::default().get_tracer("component-name");
+    /// let otel_layer = OpenTelemetryLayer::<Registry, _>::builder(tracer)
+    ///     .with_threads(true)
+    ///     .build();
+    /// ```
+    pub fn with_threads(mut self, threads: bool) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// Sets how events which are not in the context of any span are recorded.
+    /// Defaults to [`OrphanEvents::Drop`].
+    ///
+    /// ```rust,no_run
+    /// use opentelemetry::{api::Provider, sdk};
+    /// use std::time::Duration;
+    /// use tracing_opentelemetry::{OpenTelemetryLayer, OrphanEvents};
+    /// use tracing_subscriber::Registry;
+    ///
+    /// // Export each event outside of spans as its own span
+    /// let tracer = sdk::Provider::default().get_tracer("component-name");
+    /// let otel_layer = OpenTelemetryLayer::<Registry, _>::builder(tracer)
+    ///     .with_orphan_events(OrphanEvents::Span)
+    ///     .build();
+    ///
+    /// // Or collect them in a span per thread, exported every 10 seconds
+    /// let tracer = sdk::Provider::default().get_tracer("component-name");
+    /// let otel_layer = OpenTelemetryLayer::<Registry, _>::builder(tracer)
+    ///     .with_orphan_events(OrphanEvents::ThreadSpan(Duration::from_secs(10)))
+    ///     .build();
+    /// ```
+    ///
+    /// [`OrphanEvents::Drop`]: enum.OrphanEvents.html#variant.Drop
+    pub fn with_orphan_events(mut self, orphan_events: OrphanEvents) -> Self {
+        self.orphan_events = orphan_events;
+        self
+    }
+
+    /// Sets the most verbose level of events which are recorded as
+    /// OpenTelemetry span events. Defaults to `LevelFilter::TRACE`.
+    ///
+    /// This only affects the events recorded by this layer, other layers
+    /// still receive all enabled events.
+    ///
+    /// ```rust,no_run
+    /// use opentelemetry::{api::Provider, sdk};
+    /// use tracing_opentelemetry::OpenTelemetryLayer;
+    /// use tracing_subscriber::filter::LevelFilter;
+    /// use tracing_subscriber::Registry;
+    ///
+    /// let tracer = sdk::Provider::default().get_tracer("component-name");
+    /// let otel_layer = OpenTelemetryLayer::<Registry, _>::builder(tracer)
+    ///     .with_event_level(LevelFilter::INFO)
+    ///     .build();
+    /// ```
+    pub fn with_event_level(mut self, event_level: LevelFilter) -> Self {
+        self.event_level = event_level;
+        self
+    }
+
+    /// Sets a filter on the target of events which are recorded as
+    /// OpenTelemetry span events. Events are recorded if the filter returns
+    /// `true` for their target. By default events of all targets are recorded.
+    ///
+    /// This only affects the events recorded by this layer, other layers
+    /// still receive all enabled events.
+    ///
+    /// ```rust,no_run
+    /// use opentelemetry::{api::Provider, sdk};
+    /// use tracing_opentelemetry::OpenTelemetryLayer;
+    /// use tracing_subscriber::Registry;
+    ///
+    /// let tracer = sdk::Provider::default().get_tracer("component-name");
+    /// let otel_layer = OpenTelemetryLayer::<Registry, _>::builder(tracer)
+    ///     .with_event_target_filter(|target| !target.starts_with("hyper"))
+    ///     .build();
+    /// ```
+    pub fn with_event_target_filter<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
+    {
+        self.event_target_filter = Some(Box::new(filter));
+        self
+    }
+
+    /// Sets the maximum number of events recorded in each span. Additional
+    /// events are dropped according to the configured [`EventRetention`] and
+    /// counted in the `otel.dropped_events_count` attribute. By default the
+    /// number of events is not limited.
+    ///
+    /// ```rust,no_run
+    /// use opentelemetry::{api::Provider, sdk};
+    /// use tracing_opentelemetry::{EventRetention, OpenTelemetryLayer};
+    /// use tracing_subscriber::Registry;
+    ///
+    /// // Keep the last 128 events of each span
+    /// let tracer = sdk::Provider::default().get_tracer("component-name");
+    /// let otel_layer = OpenTelemetryLayer::<Registry, _>::builder(tracer)
+    ///     .with_max_events_per_span(128)
+    ///     .with_event_retention(EventRetention::Last)
+    ///     .build();
+    /// ```
+    ///
+    /// [`EventRetention`]: enum.EventRetention.html
+    pub fn with_max_events_per_span(mut self, max_events: usize) -> Self {
+        self.limits.max_events = Some(max_events);
+        self
+    }
+
+    /// Sets which events are kept once a span has reached its maximum number
+    /// of events. Defaults to [`EventRetention::First`].
+    ///
+    /// [`EventRetention::First`]: enum.EventRetention.html#variant.First
+    pub fn with_event_retention(mut self, event_retention: EventRetention) -> Self {
+        self.limits.event_retention = event_retention;
+        self
+    }
+
+    /// Sets the maximum number of attributes recorded in each span.
+    /// Additional attributes are dropped and counted in the
+    /// `otel.dropped_attributes_count` attribute. By default the number of
+    /// attributes is not limited.
+    ///
+    /// ```rust,no_run
+    /// use opentelemetry::{api::Provider, sdk};
+    /// use tracing_opentelemetry::OpenTelemetryLayer;
+    /// use tracing_subscriber::Registry;
+    ///
+    /// let tracer = sdk::Provider::default().get_tracer("component-name");
+    /// let otel_layer = OpenTelemetryLayer::<Registry, _>::builder(tracer)
+    ///     .with_max_attributes_per_span(32)
+    ///     .build();
+    /// ```
+    pub fn with_max_attributes_per_span(mut self, max_attributes: usize) -> Self {
+        self.limits.max_attributes = Some(max_attributes);
+        self
+    }
+
+    /// Sets the maximum length in bytes of string and byte array values of
+    /// span and event attributes. Longer values are truncated. By default the
+    /// length of values is not limited.
+    ///
+    /// ```rust,no_run
+    /// use opentelemetry::{api::Provider, sdk};
+    /// use tracing_opentelemetry::OpenTelemetryLayer;
+    /// use tracing_subscriber::Registry;
+    ///
+    /// let tracer = sdk::Provider::default().get_tracer("component-name");
+    /// let otel_layer = OpenTelemetryLayer::<Registry, _>::builder(tracer)
+    ///     .with_max_attribute_value_length(1024)
+    ///     .build();
+    /// ```
+    pub fn with_max_attribute_value_length(mut self, max_length: usize) -> Self {
+        self.limits.max_attribute_value_length = Some(max_length);
+        self
+    }
+
+    /// Sets the handler for internal errors. The layer recovers from these by
+    /// skipping the affected operation instead of panicking. By default
+    /// errors are printed to stderr.
+    ///
+    /// ```rust,no_run
+    /// use opentelemetry::{api::Provider, sdk};
+    /// use tracing_opentelemetry::OpenTelemetryLayer;
+    /// use tracing_subscriber::Registry;
+    ///
+    /// // Ignore internal errors
+    /// let tracer = sdk::Provider::default().get_tracer("component-name");
+    /// let otel_layer = OpenTelemetryLayer::<Registry, _>::builder(tracer)
+    ///     .with_error_handler(|_error| {})
+    ///     .build();
+    /// ```
+    pub fn with_error_handler<F>(mut self, error_handler: F) -> Self
+    where
+        F: Fn(Error) + Send + Sync + 'static,
+    {
+        self.error_handler = Box::new(error_handler);
+        self
+    }
+
+    /// Sets the baggage entries which are copied onto span attributes with
+    /// the same key when the span closes. By default no entries are copied.
+    ///
+    /// ```rust,no_run
+    /// use opentelemetry::{api::Provider, sdk};
+    /// use tracing_opentelemetry::OpenTelemetryLayer;
+    /// use tracing_subscriber::Registry;
+    ///
+    /// let tracer = sdk::Provider::default().get_tracer("component-name");
+    /// let otel_layer = OpenTelemetryLayer::<Registry, _>::builder(tracer)
+    ///     .with_baggage_attributes(vec!["tenant_id", "user_tier"])
+    ///     .build();
+    /// ```
+    pub fn with_baggage_attributes<I, K>(mut self, keys: I) -> Self
+    where
+        I: IntoIterator<Item = K>,
+        K: Into<String>,
+    {
+        self.baggage_attributes = keys.into_iter().map(Into::into).collect();
+        self
+    }
+}
+
+impl<S, T: PreSampledTracer> OpenTelemetryLayerBuilder<S, T> {
+    /// Make the sampling decision of spans with the tracer's
+    /// [`PreSampledTracer`] implementation when they are created, for tracers
+    /// other than `sdk::Tracer` whose sampler the layer does not know.
+    ///
+    /// ```rust,no_run
+    /// use opentelemetry::{api::Provider, global};
+    /// use tracing_opentelemetry::OpenTelemetryLayer;
+    /// use tracing_subscriber::Registry;
+    ///
+    /// let tracer = global::trace_provider().get_tracer("component-name");
+    /// let otel_layer = OpenTelemetryLayer::<Registry, _>::builder(tracer)
+    ///     .with_pre_sampling()
+    ///     .build();
+    /// ```
+    ///
+    /// [`PreSampledTracer`]: trait.PreSampledTracer.html
+    pub fn with_pre_sampling(mut self) -> Self {
+        self.pre_sample = |tracer, builder| Some(tracer.should_sample(builder));
+        self
+    }
+}
+
+impl<S, T> OpenTelemetryLayerBuilder<S, T>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+    T: api::Tracer + 'static,
+{
+    /// Build the configured `OpenTelemetryLayer`.
+    pub fn build(self) -> OpenTelemetryLayer<S, T> {
+        OpenTelemetryLayer {
+            tracers: self.tracers,
+            pre_sample: self.pre_sample,
+            id_generator: self.id_generator,
+            tracked_inactivity: self.tracked_inactivity,
+            location: self.location,
+            threads: self.threads,
+            orphan_events: self.orphan_events,
+            event_level: self.event_level,
+            event_target_filter: self.event_target_filter,
+            limits: self.limits,
+            error_handler: self.error_handler,
+            baggage_attributes: self.baggage_attributes,
+            orphan_spans: Mutex::new(HashMap::new()),
+            get_context: WithContext(OpenTelemetryLayer::<S, T>::get_context),
+            _registry: marker::PhantomData,
         }
     }
 }
@@ -190,73 +1580,193 @@ where
     /// Creates an `OpenTelemetry` `Span` for the corresponding `tracing` `Span`.
     /// This will attempt to parse the parent context if possible from the given attributes.
     fn new_span(&self, attrs: &Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
-        let span = ctx.span(id).expect("Span not found, this is a bug");
-        let mut extensions = span.extensions_mut();
+        let _in_layer = InLayer::enter();
+        let span = match self.span(id, &ctx) {
+            Some(span) => span,
+            None => return,
+        };
 
-        let mut builder = self
-            .tracer
+        let target = attrs.metadata().target();
+        let tracer = self.tracers.get(target);
+        let mut builder = tracer
             .span_builder(attrs.metadata().name())
             .with_start_time(SystemTime::now())
             // Eagerly assign span id so children have stable parent id
-            .with_span_id(api::SpanId::from_u64(rand::random()));
-        builder.parent_context = self.parent_context(attrs, &ctx);
+            .with_span_id(self.id_generator.new_span_id());
+        let (parent_context, baggage) = match self.parent_context(attrs, &ctx) {
+            Some((parent_context, baggage)) => (Some(parent_context), baggage),
+            None => (None, Baggage::new()),
+        };
+        builder.parent_context = parent_context;
 
         // Ensure trace id exists so children are matched properly.
         if builder.parent_context.is_none() {
-            builder.trace_id = Some(api::TraceId::from_u128(rand::random()));
+            builder.trace_id = Some(self.id_generator.new_trace_id());
         }
 
-        attrs.record(&mut SpanAttributeVisitor(&mut builder));
-        extensions.insert(builder);
+        let mut data = OtelData::new(builder, target);
+        data.local_parent = data.builder.parent_context.is_some();
+        data.baggage = baggage;
+
+        if self.location {
+            for attribute in location_attributes(attrs.metadata()) {
+                data.push_attribute(attribute, &self.limits);
+            }
+        }
+
+        if self.threads {
+            for attribute in thread_attributes() {
+                data.push_attribute(attribute, &self.limits);
+            }
+        }
+
+        let mut fields = SpanAttributeVisitor::default();
+        attrs.record(&mut fields);
+        data.record_fields(fields, &self.limits);
+        data.sample(&|builder| (self.pre_sample)(&tracer, builder));
+
+        let mut extensions = span.extensions_mut();
+        extensions.insert(data);
+
+        if self.tracked_inactivity {
+            extensions.insert(Timings::new());
+        }
+    }
+
+    /// Track the time spent outside of the span before it was entered.
+    fn on_enter(&self, id: &span::Id, ctx: Context<'_, S>) {
+        let _in_layer = InLayer::enter();
+        if !self.tracked_inactivity {
+            return;
+        }
+
+        let span = match self.span(id, &ctx) {
+            Some(span) => span,
+            None => return,
+        };
+        let mut extensions = span.extensions_mut();
+        if let Some(timings) = extensions.get_mut::<Timings>() {
+            let now = Instant::now();
+            timings.idle += (now - timings.last).as_nanos() as u64;
+            timings.last = now;
+        }
+    }
+
+    /// Track the time spent inside of the span before it was exited.
+    fn on_exit(&self, id: &span::Id, ctx: Context<'_, S>) {
+        let _in_layer = InLayer::enter();
+        if !self.tracked_inactivity {
+            return;
+        }
+
+        let span = match self.span(id, &ctx) {
+            Some(span) => span,
+            None => return,
+        };
+        let mut extensions = span.extensions_mut();
+        if let Some(timings) = extensions.get_mut::<Timings>() {
+            let now = Instant::now();
+            timings.busy += (now - timings.last).as_nanos() as u64;
+            timings.last = now;
+        }
     }
 
     /// Record values for the given span.
     fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
-        let span = ctx.span(id).expect("Span not found, this is a bug");
+        let _in_layer = InLayer::enter();
+        let span = match self.span(id, &ctx) {
+            Some(span) => span,
+            None => return,
+        };
+        let mut fields = SpanAttributeVisitor::default();
+        values.record(&mut fields);
         let mut extensions = span.extensions_mut();
-        if let Some(builder) = extensions.get_mut::<api::SpanBuilder>() {
-            values.record(&mut SpanAttributeVisitor(builder));
+        if let Some(data) = extensions.get_mut::<OtelData>() {
+            data.record_fields(fields, &self.limits);
+        }
+    }
+
+    /// Record `follows_from` relationships between spans as OpenTelemetry
+    /// span links.
+    fn on_follows_from(&self, id: &Id, follows: &Id, ctx: Context<'_, S>) {
+        let _in_layer = InLayer::enter();
+        // Resolve the linked context first, `follows` may be the same span.
+        // Its trace is resolved through its ancestors, as for `get_context`.
+        let follows_context = match self
+            .span(follows, &ctx)
+            .and_then(|follows| Self::resolve_context(&follows))
+        {
+            Some(follows_context) => follows_context,
+            None => return,
+        };
+
+        let span = match self.span(id, &ctx) {
+            Some(span) => span,
+            None => return,
+        };
+        let mut extensions = span.extensions_mut();
+        if let Some(data) = extensions.get_mut::<OtelData>() {
+            data.push_link(api::Link::new(follows_context, Vec::new()));
         }
     }
 
     /// Record logs for the given event.
     fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
-        // Ignore events that are not in the context of a span
-        if let Some(span_id) = ctx.current_span().id() {
-            let span = ctx.span(span_id).expect("Span not found, this is a bug");
-            let mut extensions = span.extensions_mut();
-            if let Some(builder) = extensions.get_mut::<api::SpanBuilder>() {
-                let mut otel_event = api::Event::new(
-                    String::new(),
-                    SystemTime::now(),
-                    vec![
-                        api::Key::new("level").string(event.metadata().level().to_string()),
-                        api::Key::new("target").string(event.metadata().target()),
-                    ],
-                );
-
-                if builder.status_code.is_none() && *event.metadata().level() == tracing_core::Level::ERROR {
-                    builder.status_code = Some(api::StatusCode::Unknown);
-                }
+        let _in_layer = InLayer::enter();
+        if !self.event_enabled(event.metadata()) {
+            return;
+        }
 
-                event.record(&mut SpanEventVisitor(&mut otel_event));
+        let current = ctx.current_span();
+        // Events with an explicit parent are recorded in that span, contextual
+        // events in the current span, and explicit root events in no span.
+        let span_id = if let Some(parent) = event.parent() {
+            Some(parent)
+        } else if event.is_contextual() {
+            current.id()
+        } else {
+            None
+        };
 
-                if let Some(ref mut events) = builder.message_events {
-                    events.push(otel_event);
-                } else {
-                    builder.message_events = Some(vec![otel_event]);
-                }
+        if let Some(span_id) = span_id {
+            let span = match self.span(span_id, &ctx) {
+                Some(span) => span,
+                None => return,
+            };
+            let otel_event = self.convert_event(event);
+            let mut extensions = span.extensions_mut();
+            if let Some(data) = extensions.get_mut::<OtelData>() {
+                self.record_event(data, otel_event);
             }
-        };
+        } else {
+            self.record_orphan_event(event);
+        }
     }
 
     /// Mark the `Span` as ended when it is closed.
     fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
-        let span = ctx.span(&id).expect("Span not found, this is a bug");
+        let _in_layer = InLayer::enter();
+        let span = match self.span(&id, &ctx) {
+            Some(span) => span,
+            None => return,
+        };
         let mut extensions = span.extensions_mut();
-        if let Some(builder) = extensions.remove::<api::SpanBuilder>() {
-            // Assign end time, build and start span, drop span to export
-            builder.with_end_time(SystemTime::now()).start(&self.tracer);
+        if let Some(mut data) = extensions.remove::<OtelData>() {
+            Self::resolve_parent_context(&span, &mut data);
+
+            for key in &self.baggage_attributes {
+                if let Some(value) = data.baggage.get(key).map(str::to_string) {
+                    data.push_attribute(api::Key::new(key.clone()).string(value), &self.limits);
+                }
+            }
+
+            if let Some(timings) = extensions.remove::<Timings>() {
+                let idle = timings.idle + (Instant::now() - timings.last).as_nanos() as u64;
+                data.push_attribute(api::Key::new("busy_ns").u64(timings.busy), &self.limits);
+                data.push_attribute(api::Key::new("idle_ns").u64(idle), &self.limits);
+            }
+
+            self.export(data, SystemTime::now());
         }
     }
 
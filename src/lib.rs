@@ -31,13 +31,63 @@
 //!     });
 //! }
 //! ```
+//!
+//! ## Special Fields
+//!
+//! Fields with an `otel.` prefix are reserved for this crate and configure
+//! the OpenTelemetry span instead of being recorded as attributes:
+//!
+//! * `otel.name`: Override the span name, which defaults to the name of the
+//!   `tracing` span. It may also be set after the span was created with
+//!   `Span::record`, e.g. once an HTTP route has been matched.
+//! * `otel.kind`: Set the span kind to one of `server`, `client`, `producer`,
+//!   `consumer` or `internal`. e.g. `span!(Level::INFO, "request", otel.kind = "server")`
+//! * `otel.status_code`: Set the span status code, either by name such as
+//!   `"InvalidArgument"` or by its numeric value. Unknown values leave the
+//!   status unchanged. Spans without an explicit status are marked `Unknown`
+//!   when an `ERROR` event is recorded in them.
+//! * `otel.status_message`: Set the span status message.
+//!
+//! ## Errors
+//!
+//! Fields recorded as errors, e.g. `error = &err as &dyn std::error::Error`,
+//! add an `exception` event to the span, and mark spans without an explicit
+//! status as `Unknown`. The event has an `exception.message` attribute, and
+//! an `exception.stacktrace` attribute listing the sources of the error. The
+//! concrete type of an error is not known, so `exception.type` is only
+//! recorded when given as a field along with the error:
+//!
+//! ```
+//! let err = std::io::Error::new(std::io::ErrorKind::Other, "oh no!");
+//! tracing::error!(error = &err as &dyn std::error::Error, exception.type = "std::io::Error");
+//! ```
+//!
+//! Panics can be recorded the same way by installing a [`PanicHook`].
+//!
+//! [`PanicHook`]: struct.PanicHook.html
 #![deny(unreachable_pub)]
 #![cfg_attr(test, deny(warnings))]
 
+/// Baggage carried by spans and its W3C propagation.
+mod baggage;
+/// Internal errors reported by the layer.
+mod error;
+/// Generators for OpenTelemetry trace and span ids.
+mod id_generator;
 /// Implementation of the trace::Layer as a source of OpenTelemetry data.
 mod layer;
+/// Panic hook which records panics on the current OpenTelemetry span.
+mod panic;
 /// Span extension which enables OpenTelemetry span context management.
 mod span_ext;
+/// Tracers which can make sampling decisions before spans are built, and
+/// routing of spans to tracers by target.
+mod tracer;
 
-pub use layer::OpenTelemetryLayer;
+pub use baggage::{Baggage, BaggagePropagator};
+pub use error::Error;
+pub use id_generator::{IdGenerator, RandomIdGenerator};
+pub use layer::{EventRetention, OpenTelemetryLayer, OpenTelemetryLayerBuilder, OrphanEvents};
+pub use panic::PanicHook;
 pub use span_ext::OpenTelemetrySpanExt;
+pub use tracer::{PreSampledTracer, TracerRouter};
@@ -0,0 +1,98 @@
+use crate::layer::{in_layer, WithContext};
+use opentelemetry::api;
+#[cfg(feature = "backtrace")]
+use std::backtrace::Backtrace;
+use std::panic;
+use std::time::SystemTime;
+
+/// A panic hook which records panics as `exception` events on the current
+/// `tracing` span and marks its OpenTelemetry span as failed, replacing any
+/// status set before.
+///
+/// The previously installed panic hook is called afterwards, so the default
+/// panic output is unchanged. Panics raised while the layer itself is
+/// handling a span, e.g. in the `Debug` impl of a recorded field, are not
+/// recorded. Spans entered by the panicking thread are closed and exported as
+/// usual while unwinding.
+///
+/// ```rust
+/// use tracing_opentelemetry::PanicHook;
+///
+/// PanicHook::new().install();
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct PanicHook {
+    #[cfg(feature = "backtrace")]
+    backtrace: bool,
+}
+
+impl PanicHook {
+    /// Create a new panic hook which does not capture backtraces.
+    pub fn new() -> Self {
+        PanicHook::default()
+    }
+
+    /// Sets whether a backtrace is captured and recorded in the
+    /// `exception.stacktrace` attribute. Backtraces are captured regardless of
+    /// the `RUST_BACKTRACE` environment variable.
+    ///
+    /// Requires the `backtrace` feature, which needs Rust 1.65 or newer.
+    #[cfg(feature = "backtrace")]
+    pub fn with_backtrace(mut self, backtrace: bool) -> Self {
+        self.backtrace = backtrace;
+        self
+    }
+
+    /// Install the panic hook, replacing and chaining to the current one.
+    pub fn install(self) {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            // A panic inside the layer may hold the lock on the span data, so
+            // it is not recorded rather than risking a deadlock.
+            let span = tracing::Span::current();
+            if !span.is_none() && !in_layer() {
+                let message = if let Some(message) = info.payload().downcast_ref::<&str>() {
+                    message.to_string()
+                } else if let Some(message) = info.payload().downcast_ref::<String>() {
+                    message.clone()
+                } else {
+                    "Box<dyn Any>".to_string()
+                };
+
+                let mut attributes = vec![
+                    api::Key::new("exception.type").string("panic"),
+                    api::Key::new("exception.message").string(message),
+                ];
+                if let Some(location) = info.location() {
+                    attributes.push(api::Key::new("code.filepath").string(location.file()));
+                    attributes.push(api::Key::new("code.lineno").i64(location.line() as i64));
+                }
+                #[cfg(feature = "backtrace")]
+                if self.backtrace {
+                    attributes.push(
+                        api::Key::new("exception.stacktrace")
+                            .string(Backtrace::force_capture().to_string()),
+                    );
+                }
+
+                let mut exception = Some(api::Event::new(
+                    "exception".to_string(),
+                    SystemTime::now(),
+                    attributes,
+                ));
+                span.with_subscriber(|(id, subscriber)| {
+                    if let Some(get_context) = subscriber.downcast_ref::<WithContext>() {
+                        get_context.with_context(subscriber, id, |data, _sampler, limits| {
+                            if let Some(exception) = exception.take() {
+                                data.push_event(exception, limits);
+                                data.builder.status_code = Some(api::StatusCode::Unknown);
+                            }
+                        });
+                    }
+                });
+            }
+
+            previous(info);
+        }));
+    }
+}
@@ -1,4 +1,5 @@
 use crate::layer::{build_context, WithContext};
+use crate::Baggage;
 use opentelemetry::api;
 
 /// `OpenTelemetrySpanExt` allows tracing spans to accept and return
@@ -7,6 +8,16 @@ pub trait OpenTelemetrySpanExt {
     /// Associates `self` with a given `OpenTelemetry` trace, using
     /// the provided parent context.
     ///
+    /// The parent context is kept as given, including whether it is remote and
+    /// its trace flags, so the sampling decision and the context of `self` and
+    /// its children reflect it. Invalid contexts, e.g. when no context could be
+    /// extracted from a request, are ignored. Trace state is not part of
+    /// `SpanContext` in this version of OpenTelemetry and cannot be kept.
+    ///
+    /// Descendants of `self` which are still open join the new trace, even if
+    /// they were created before this call. Descendants which already closed
+    /// remain in the previous trace.
+    ///
     /// ```rust
     /// use opentelemetry::api::{self, HttpTextFormat};
     /// use tracing_opentelemetry::OpenTelemetrySpanExt;
@@ -57,15 +68,98 @@ pub trait OpenTelemetrySpanExt {
     /// make_request(Span::current().context())
     /// ```
     fn context(&self) -> api::SpanContext;
+
+    /// Sets a baggage entry on `self`. Child spans created afterwards inherit
+    /// the baggage of `self`.
+    ///
+    /// ```rust
+    /// use tracing_opentelemetry::OpenTelemetrySpanExt;
+    ///
+    /// let request = tracing::info_span!("request");
+    /// request.set_baggage("tenant_id", "acme");
+    ///
+    /// let _guard = request.enter();
+    /// let query = tracing::info_span!("query");
+    /// // With an `OpenTelemetryLayer` installed, `query` inherits `tenant_id`
+    /// let tenant_id = query.baggage().get("tenant_id").map(str::to_string);
+    /// ```
+    fn set_baggage(&self, key: &str, value: &str);
+
+    /// Returns the baggage of `self`, e.g. to inject it into an outgoing
+    /// request with a [`BaggagePropagator`].
+    ///
+    /// [`BaggagePropagator`]: struct.BaggagePropagator.html
+    fn baggage(&self) -> Baggage;
+
+    /// Links `self` to the span with the given context, e.g. to a remote span
+    /// which produced a message being processed. Invalid contexts are
+    /// ignored.
+    ///
+    /// ```rust
+    /// use opentelemetry::api::{self, HttpTextFormat};
+    /// use std::collections::HashMap;
+    /// use tracing_opentelemetry::OpenTelemetrySpanExt;
+    ///
+    /// // Example records of a consumed batch, with headers that impl `api::Carrier`.
+    /// let records: Vec<HashMap<&'static str, String>> = Vec::new();
+    /// let propagator = api::TraceContextPropagator::new();
+    ///
+    /// let batch = tracing::info_span!("process_batch");
+    /// for headers in &records {
+    ///     batch.add_link(propagator.extract(headers));
+    /// }
+    /// ```
+    fn add_link(&self, span_context: api::SpanContext);
+
+    /// Links `self` to the span with the given context, with attributes
+    /// describing the link. Invalid contexts are ignored.
+    fn add_link_with_attributes(
+        &self,
+        span_context: api::SpanContext,
+        attributes: Vec<api::KeyValue>,
+    );
+
+    /// Sets an attribute on `self`, replacing any previous value with the
+    /// same key. Unlike `tracing` fields, the key does not have to be known
+    /// at the callsite.
+    ///
+    /// The reserved `otel.name`, `otel.kind`, `otel.status_code` and
+    /// `otel.status_message` keys configure the span as they do when recorded
+    /// as `tracing` fields, and are not added as attributes.
+    ///
+    /// ```rust
+    /// use opentelemetry::api;
+    /// use tracing_opentelemetry::OpenTelemetrySpanExt;
+    ///
+    /// let request = tracing::info_span!("request");
+    /// let header = "user-agent";
+    /// request.set_attribute(
+    ///     api::Key::new(format!("http.request.header.{}", header)).string("curl/7.68.0"),
+    /// );
+    /// ```
+    fn set_attribute(&self, attribute: api::KeyValue);
+
+    /// Sets multiple attributes on `self`, replacing any previous values with
+    /// the same keys.
+    fn set_attributes(&self, attributes: impl IntoIterator<Item = api::KeyValue>)
+    where
+        Self: Sized;
 }
 
 impl OpenTelemetrySpanExt for tracing::Span {
     fn set_parent(&self, parent_context: api::SpanContext) {
+        // Keep the span in its own trace rather than the invalid one.
+        if !parent_context.is_valid() {
+            return;
+        }
+
         self.with_subscriber(move |(id, subscriber)| {
             let mut parent_context = Some(parent_context);
             if let Some(get_context) = subscriber.downcast_ref::<WithContext>() {
-                get_context.with_context(subscriber, id, move |builder| {
-                    builder.parent_context = parent_context.take()
+                get_context.with_context(subscriber, id, move |data, sampler, _limits| {
+                    if let Some(parent_context) = parent_context.take() {
+                        data.set_parent(parent_context, sampler);
+                    }
                 });
             }
         });
@@ -75,12 +169,83 @@ impl OpenTelemetrySpanExt for tracing::Span {
         let mut span_context = None;
         self.with_subscriber(|(id, subscriber)| {
             if let Some(get_context) = subscriber.downcast_ref::<WithContext>() {
-                get_context.with_context(subscriber, id, |builder| {
-                    span_context = Some(build_context(builder));
+                get_context.with_context(subscriber, id, |data, _sampler, _limits| {
+                    span_context = Some(build_context(data));
                 })
             }
         });
 
         span_context.unwrap_or_else(api::SpanContext::empty_context)
     }
+
+    fn set_baggage(&self, key: &str, value: &str) {
+        self.with_subscriber(|(id, subscriber)| {
+            if let Some(get_context) = subscriber.downcast_ref::<WithContext>() {
+                get_context.with_context(subscriber, id, |data, _sampler, _limits| {
+                    data.baggage.insert(key, value);
+                })
+            }
+        });
+    }
+
+    fn baggage(&self) -> Baggage {
+        let mut baggage = None;
+        self.with_subscriber(|(id, subscriber)| {
+            if let Some(get_context) = subscriber.downcast_ref::<WithContext>() {
+                get_context.with_context(subscriber, id, |data, _sampler, _limits| {
+                    baggage = Some(data.baggage.clone());
+                })
+            }
+        });
+
+        baggage.unwrap_or_default()
+    }
+
+    fn add_link(&self, span_context: api::SpanContext) {
+        self.add_link_with_attributes(span_context, Vec::new())
+    }
+
+    fn add_link_with_attributes(
+        &self,
+        span_context: api::SpanContext,
+        attributes: Vec<api::KeyValue>,
+    ) {
+        if !span_context.is_valid() {
+            return;
+        }
+
+        self.with_subscriber(move |(id, subscriber)| {
+            let mut link = Some(api::Link::new(span_context, attributes));
+            if let Some(get_context) = subscriber.downcast_ref::<WithContext>() {
+                get_context.with_context(subscriber, id, move |data, _sampler, _limits| {
+                    if let Some(link) = link.take() {
+                        data.push_link(link);
+                    }
+                });
+            }
+        });
+    }
+
+    fn set_attribute(&self, attribute: api::KeyValue) {
+        self.set_attributes(Some(attribute))
+    }
+
+    fn set_attributes(&self, attributes: impl IntoIterator<Item = api::KeyValue>)
+    where
+        Self: Sized,
+    {
+        // Collect the attributes first, so that the span is not locked while
+        // the iterator runs.
+        let attributes: Vec<api::KeyValue> = attributes.into_iter().collect();
+        self.with_subscriber(move |(id, subscriber)| {
+            let mut attributes = Some(attributes);
+            if let Some(get_context) = subscriber.downcast_ref::<WithContext>() {
+                get_context.with_context(subscriber, id, move |data, _sampler, limits| {
+                    for attribute in attributes.take().into_iter().flatten() {
+                        data.record_attribute(attribute, limits);
+                    }
+                });
+            }
+        });
+    }
 }
@@ -0,0 +1,214 @@
+use opentelemetry::{api, global, sdk};
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::{Arc, RwLock};
+
+/// An interface for tracers that can make a sampling decision before the
+/// OpenTelemetry span is built.
+///
+/// `tracing` spans are converted to OpenTelemetry spans only once they close,
+/// but their [`SpanContext`] must be available to children and propagators
+/// while they are still open. Implementing this trait lets the layer make a
+/// sampling decision when the span is created, so that the propagated trace
+/// flags reflect it, and skip building spans which are not sampled.
+///
+/// The layer uses this trait for `sdk::Tracer` and `api::NoopTracer` on its
+/// own. Other tracers implementing it are only used for sampling once
+/// enabled with [`OpenTelemetryLayerBuilder::with_pre_sampling`]. Without a
+/// sampling decision, spans without a parent are assumed to be sampled and
+/// spans with a remote parent inherit its trace flags.
+///
+/// Spans with a remote parent are built with the decision made here. An
+/// `sdk::Tracer` still samples spans without a parent again when they are
+/// built, which agrees with the decision made here for deterministic
+/// samplers such as those of `sdk::Sampler`.
+///
+/// [`SpanContext`]: https://docs.rs/opentelemetry/latest/opentelemetry/api/trace/span_context/struct.SpanContext.html
+/// [`OpenTelemetryLayerBuilder::with_pre_sampling`]: struct.OpenTelemetryLayerBuilder.html#method.with_pre_sampling
+pub trait PreSampledTracer {
+    /// Returns the sampling result for a span that will be created from the
+    /// given `builder`.
+    fn should_sample(&self, builder: &api::SpanBuilder) -> api::SamplingResult;
+}
+
+impl PreSampledTracer for sdk::Tracer {
+    /// Uses the sampler configured on the tracer's `Provider`, which the
+    /// tracer consults again when building spans without a parent.
+    fn should_sample(&self, builder: &api::SpanBuilder) -> api::SamplingResult {
+        let trace_id = builder
+            .parent_context
+            .as_ref()
+            .map(|parent_context| parent_context.trace_id())
+            .or(builder.trace_id)
+            .unwrap_or_else(api::TraceId::invalid);
+
+        self.provider().config().default_sampler.should_sample(
+            builder.parent_context.as_ref(),
+            trace_id,
+            builder.span_id.unwrap_or_else(api::SpanId::invalid),
+            &builder.name,
+            builder
+                .span_kind
+                .as_ref()
+                .unwrap_or(&api::SpanKind::Internal),
+            builder.attributes.as_deref().unwrap_or(&[]),
+            builder.links.as_deref().unwrap_or(&[]),
+        )
+    }
+}
+
+impl PreSampledTracer for api::NoopTracer {
+    /// Spans created by a `NoopTracer` are never recorded.
+    fn should_sample(&self, _builder: &api::SpanBuilder) -> api::SamplingResult {
+        api::SamplingResult {
+            decision: api::SamplingDecision::NotRecord,
+            attributes: Vec::new(),
+        }
+    }
+}
+
+impl PreSampledTracer for global::BoxedTracer {
+    /// The sampler of a boxed tracer is not accessible, so it is ignored and
+    /// all spans are assumed to be sampled.
+    fn should_sample(&self, _builder: &api::SpanBuilder) -> api::SamplingResult {
+        api::SamplingResult {
+            decision: api::SamplingDecision::RecordAndSampled,
+            attributes: Vec::new(),
+        }
+    }
+}
+
+/// Make the sampling decision for a span with the given `tracer` if it is
+/// one of the tracers whose sampler is known to the layer.
+pub(crate) fn pre_sample<T: 'static>(
+    tracer: &T,
+    builder: &api::SpanBuilder,
+) -> Option<api::SamplingResult> {
+    let tracer = tracer as &dyn Any;
+    if let Some(tracer) = tracer.downcast_ref::<sdk::Tracer>() {
+        Some(tracer.should_sample(builder))
+    } else {
+        tracer
+            .downcast_ref::<api::NoopTracer>()
+            .map(|tracer| tracer.should_sample(builder))
+    }
+}
+
+/// The tracer, or tracers routed by target, that a layer creates spans with.
+pub(crate) enum Tracers<T> {
+    /// All spans are created with a single tracer.
+    Single(T),
+    /// Spans are created with a tracer per target.
+    Routed(TracerRouter<T>),
+}
+
+impl<T> Tracers<T> {
+    /// Returns the tracer for spans with the given target.
+    pub(crate) fn get(&self, target: &'static str) -> TracerRef<'_, T> {
+        match self {
+            Tracers::Single(tracer) => TracerRef::Borrowed(tracer),
+            Tracers::Routed(router) => TracerRef::Shared(router.get(target)),
+        }
+    }
+}
+
+/// Creates tracers of a `Provider` for an [`OpenTelemetryLayer`] built with
+/// [`OpenTelemetryLayer::routed_builder`], so that each component shows up
+/// as its own instrumentation library.
+///
+/// Spans use a tracer named after their target, or after the longest
+/// matching prefix added with [`with_prefix`]. Tracers are created on first
+/// use and cached per target. OpenTelemetry tracers of this version are
+/// identified by name only and carry no version.
+///
+/// ```rust
+/// use opentelemetry::sdk;
+/// use tracing_opentelemetry::TracerRouter;
+///
+/// let router = TracerRouter::new(sdk::Provider::default())
+///     // Spans of `my_app::db` and its submodules use the `database` tracer
+///     .with_prefix("my_app::db", "database");
+/// ```
+///
+/// [`OpenTelemetryLayer`]: struct.OpenTelemetryLayer.html
+/// [`OpenTelemetryLayer::routed_builder`]: struct.OpenTelemetryLayer.html#method.routed_builder
+/// [`with_prefix`]: #method.with_prefix
+pub struct TracerRouter<T> {
+    get_tracer: Box<dyn Fn(&'static str) -> T + Send + Sync>,
+    prefixes: Vec<(&'static str, &'static str)>,
+    tracers: RwLock<HashMap<&'static str, Arc<T>>>,
+}
+
+impl<T> TracerRouter<T> {
+    /// Create a router which creates tracers with the given `provider`.
+    pub fn new<P>(provider: P) -> Self
+    where
+        P: api::Provider<Tracer = T> + Send + Sync,
+    {
+        TracerRouter {
+            get_tracer: Box::new(move |name| provider.get_tracer(name)),
+            prefixes: Vec::new(),
+            tracers: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Use the tracer named `name` for spans whose target is `prefix` or one
+    /// of its submodules.
+    pub fn with_prefix(mut self, prefix: &'static str, name: &'static str) -> Self {
+        self.prefixes.push((prefix, name));
+        // Check longer prefixes first so the most specific one is used.
+        self.prefixes
+            .sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+        self
+    }
+
+    fn get(&self, target: &'static str) -> Arc<T> {
+        let tracers = self.tracers.read().unwrap_or_else(|err| err.into_inner());
+        if let Some(tracer) = tracers.get(target) {
+            return tracer.clone();
+        }
+        drop(tracers);
+
+        let name = self
+            .prefixes
+            .iter()
+            .find(|(prefix, _)| {
+                target.starts_with(prefix)
+                    && (target.len() == prefix.len() || target[prefix.len()..].starts_with("::"))
+            })
+            .map_or(target, |(_, name)| name);
+
+        let mut tracers = self.tracers.write().unwrap_or_else(|err| err.into_inner());
+        tracers
+            .entry(target)
+            .or_insert_with(|| Arc::new((self.get_tracer)(name)))
+            .clone()
+    }
+}
+
+impl<T> fmt::Debug for TracerRouter<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TracerRouter")
+            .field("prefixes", &self.prefixes)
+            .finish()
+    }
+}
+
+/// A tracer borrowed from a single tracer layer or shared from a router.
+pub(crate) enum TracerRef<'a, T> {
+    Borrowed(&'a T),
+    Shared(Arc<T>),
+}
+
+impl<T> Deref for TracerRef<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        match self {
+            TracerRef::Borrowed(tracer) => tracer,
+            TracerRef::Shared(tracer) => tracer,
+        }
+    }
+}
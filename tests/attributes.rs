@@ -0,0 +1,126 @@
+mod common;
+
+use opentelemetry::api;
+
+#[test]
+fn typed_values_recorded() {
+    let spans = common::export(
+        |builder| builder,
+        || {
+            let span = tracing::info_span!(
+                "request",
+                http.method = "GET",
+                http.status_code = 200u64,
+                retries = -1i64,
+                cached = false,
+                path = ?"/users",
+            );
+            let _guard = span.enter();
+            tracing::info!(bytes = 512u64, offset = -8i64, last = true, name = "chunk");
+        },
+    );
+
+    let span = &spans[0];
+    let string = |value: &str| Some(api::Value::String(value.to_string()));
+    assert_eq!(common::attribute(span, "http.method"), string("GET"));
+    assert_eq!(
+        common::attribute(span, "http.status_code"),
+        Some(api::Value::U64(200))
+    );
+    assert_eq!(
+        common::attribute(span, "retries"),
+        Some(api::Value::I64(-1))
+    );
+    assert_eq!(
+        common::attribute(span, "cached"),
+        Some(api::Value::Bool(false))
+    );
+    // Debug values are still formatted.
+    assert_eq!(common::attribute(span, "path"), string("\"/users\""));
+
+    let event = span.message_events.iter().next().unwrap();
+    assert_eq!(
+        common::event_attribute(event, "bytes"),
+        Some(api::Value::U64(512))
+    );
+    assert_eq!(
+        common::event_attribute(event, "offset"),
+        Some(api::Value::I64(-8))
+    );
+    assert_eq!(
+        common::event_attribute(event, "last"),
+        Some(api::Value::Bool(true))
+    );
+    assert_eq!(common::event_attribute(event, "name"), string("chunk"));
+}
+
+#[test]
+fn code_locations_recorded() {
+    let spans = common::export(
+        |builder| builder.with_location(true),
+        || {
+            let span = tracing::info_span!("request");
+            let _guard = span.enter();
+            tracing::info!("handled");
+        },
+    );
+
+    let span = &spans[0];
+    let event = span.message_events.iter().next().unwrap();
+    for value in &[
+        common::attribute(span, "code.filepath"),
+        common::event_attribute(event, "code.filepath"),
+    ] {
+        assert_eq!(
+            *value,
+            Some(api::Value::String("tests/attributes.rs".to_string()))
+        );
+    }
+    for value in &[
+        common::attribute(span, "code.namespace"),
+        common::event_attribute(event, "code.namespace"),
+    ] {
+        assert_eq!(*value, Some(api::Value::String("attributes".to_string())));
+    }
+    let line = |value| match value {
+        Some(api::Value::I64(line)) => line,
+        value => panic!("expected a line number, got {:?}", value),
+    };
+    assert_eq!(
+        line(common::event_attribute(event, "code.lineno")),
+        line(common::attribute(span, "code.lineno")) + 2
+    );
+}
+
+#[test]
+fn threads_recorded() {
+    let spans = std::thread::Builder::new()
+        .name("worker".to_string())
+        .spawn(|| {
+            common::export(
+                |builder| builder.with_threads(true),
+                || {
+                    let span = tracing::info_span!("request");
+                    let _guard = span.enter();
+                    tracing::info!("handled");
+                },
+            )
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+
+    let span = &spans[0];
+    let event = span.message_events.iter().next().unwrap();
+    let name = Some(api::Value::String("worker".to_string()));
+    assert_eq!(common::attribute(span, "thread.name"), name);
+    assert_eq!(common::event_attribute(event, "thread.name"), name);
+    match common::attribute(span, "thread.id") {
+        Some(api::Value::U64(_)) => {}
+        value => panic!("expected a thread id, got {:?}", value),
+    }
+    assert_eq!(
+        common::event_attribute(event, "thread.id"),
+        common::attribute(span, "thread.id")
+    );
+}
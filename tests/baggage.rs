@@ -0,0 +1,34 @@
+use opentelemetry::api;
+use std::collections::HashMap;
+use tracing_opentelemetry::{Baggage, BaggagePropagator};
+
+#[test]
+fn round_trip() {
+    let mut baggage = Baggage::new();
+    baggage.insert("tenant id", "a,b;c=d");
+    baggage.insert("quoted", "\"100%\" \\ ok");
+    baggage.insert("city", "Zürich");
+
+    let mut carrier = HashMap::new();
+    BaggagePropagator::new().inject(&baggage, &mut carrier);
+
+    let header = api::Carrier::get(&carrier, "baggage").unwrap();
+    assert!(!header.contains(' '));
+    assert_eq!(header.split(',').count(), 3);
+    assert_eq!(BaggagePropagator::new().extract(&carrier), baggage);
+}
+
+#[test]
+fn invalid_entries_skipped() {
+    let mut carrier = HashMap::new();
+    api::Carrier::set(
+        &mut carrier,
+        "baggage",
+        "valid=1;prop=x, =empty,broken=%zz,missing, spaced = 2 ".to_string(),
+    );
+
+    let baggage = BaggagePropagator::new().extract(&carrier);
+    assert_eq!(baggage.len(), 2);
+    assert_eq!(baggage.get("valid"), Some("1"));
+    assert_eq!(baggage.get("spaced"), Some("2"));
+}
@@ -0,0 +1,90 @@
+#![allow(dead_code)]
+
+use opentelemetry::exporter::trace::{ExportResult, SpanData, SpanExporter};
+use opentelemetry::{api, api::Provider, sdk};
+use std::any::Any;
+use std::sync::{Arc, Mutex};
+use tracing_opentelemetry::{OpenTelemetryLayer, OpenTelemetryLayerBuilder};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::Registry;
+
+/// An exporter which keeps all exported spans.
+#[derive(Clone, Debug, Default)]
+pub struct TestExporter(pub Arc<Mutex<Vec<Arc<SpanData>>>>);
+
+impl SpanExporter for TestExporter {
+    fn export(&self, batch: Vec<Arc<SpanData>>) -> ExportResult {
+        self.0.lock().unwrap().extend(batch);
+        ExportResult::Success
+    }
+
+    fn shutdown(&self) {}
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Returns a tracer which samples all spans and exports them to `exporter`.
+pub fn tracer(exporter: TestExporter) -> sdk::Tracer {
+    sampled_tracer(exporter, sdk::Sampler::Always)
+}
+
+/// Returns a tracer which samples spans with `sampler` and exports them to
+/// `exporter`.
+pub fn sampled_tracer(exporter: TestExporter, sampler: sdk::Sampler) -> sdk::Tracer {
+    sdk::Provider::builder()
+        .with_simple_exporter(exporter)
+        .with_config(sdk::Config {
+            default_sampler: Box::new(sampler),
+            ..Default::default()
+        })
+        .build()
+        .get_tracer("test")
+}
+
+/// Run `f` with a layer configured by `configure` as the default subscriber
+/// and return the spans exported meanwhile.
+pub fn export<C, F>(configure: C, f: F) -> Vec<Arc<SpanData>>
+where
+    C: FnOnce(
+        OpenTelemetryLayerBuilder<Registry, sdk::Tracer>,
+    ) -> OpenTelemetryLayerBuilder<Registry, sdk::Tracer>,
+    F: FnOnce(),
+{
+    let exporter = TestExporter::default();
+    let builder = OpenTelemetryLayer::builder(tracer(exporter.clone()));
+    let layer = configure(builder).build();
+    tracing::subscriber::with_default(Registry::default().with(layer), f);
+
+    let spans = exporter.0.lock().unwrap();
+    spans.clone()
+}
+
+/// Returns the value of the span attribute with the given key.
+pub fn attribute(span: &SpanData, key: &'static str) -> Option<api::Value> {
+    span.attributes.iter().find_map(|(attribute_key, value)| {
+        if *attribute_key == api::Key::new(key) {
+            Some(value.clone())
+        } else {
+            None
+        }
+    })
+}
+
+/// Returns the value of the event attribute with the given key.
+pub fn event_attribute(event: &api::Event, key: &'static str) -> Option<api::Value> {
+    event
+        .attributes
+        .iter()
+        .find(|attribute| attribute.key == api::Key::new(key))
+        .map(|attribute| attribute.value.clone())
+}
+
+/// Returns the names of the span events in the order they were recorded.
+pub fn event_names(span: &SpanData) -> Vec<String> {
+    span.message_events
+        .iter()
+        .map(|event| event.name.clone())
+        .collect()
+}
@@ -0,0 +1,61 @@
+mod common;
+
+use opentelemetry::api;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+fn remote_context() -> api::SpanContext {
+    api::SpanContext::new(
+        api::TraceId::from_u128(42),
+        api::SpanId::from_u64(42),
+        api::TRACE_FLAG_SAMPLED,
+        true,
+    )
+}
+
+#[test]
+fn children_follow_later_set_parent() {
+    let spans = common::export(
+        |builder| builder,
+        || {
+            let request = tracing::info_span!("request");
+            let _request = request.enter();
+            let query = tracing::info_span!("query");
+            let _query = query.enter();
+            let row = tracing::info_span!("row");
+
+            // The parent is only known after the children were created.
+            request.set_parent(remote_context());
+            drop(row);
+        },
+    );
+
+    let span = |name: &str| spans.iter().find(|span| span.name == name).unwrap();
+    for span in &spans {
+        assert_eq!(span.context.trace_id(), api::TraceId::from_u128(42));
+    }
+    assert_eq!(span("request").parent_span_id, api::SpanId::from_u64(42));
+    assert_eq!(
+        span("query").parent_span_id,
+        span("request").context.span_id()
+    );
+    assert_eq!(span("row").parent_span_id, span("query").context.span_id());
+}
+
+#[test]
+fn follows_from_resolves_later_set_parent() {
+    let spans = common::export(
+        |builder| builder,
+        || {
+            let request = tracing::info_span!("request");
+            let query = tracing::info_span!(parent: &request, "query");
+            request.set_parent(remote_context());
+
+            let retry = tracing::info_span!("retry");
+            retry.follows_from(&query);
+        },
+    );
+
+    let retry = spans.iter().find(|span| span.name == "retry").unwrap();
+    let link = retry.links.iter().next().unwrap();
+    assert_eq!(link.span_context().trace_id(), api::TraceId::from_u128(42));
+}
@@ -0,0 +1,125 @@
+use opentelemetry::api;
+use std::any::TypeId;
+use std::sync::{Arc, Mutex};
+use tracing::span;
+use tracing::subscriber::Interest;
+use tracing::{Event, Metadata, Subscriber};
+use tracing_opentelemetry::{Error, OpenTelemetryLayer, OpenTelemetrySpanExt};
+use tracing_subscriber::layer::{Layered, SubscriberExt};
+use tracing_subscriber::Registry;
+
+type OtelSubscriber = Layered<OpenTelemetryLayer<Registry, api::NoopTracer>, Registry>;
+
+fn subscriber() -> (OtelSubscriber, Arc<Mutex<Vec<Error>>>) {
+    let errors = Arc::new(Mutex::new(Vec::new()));
+    let handler_errors = errors.clone();
+    let layer = OpenTelemetryLayer::builder(api::NoopTracer {})
+        .with_error_handler(move |error| handler_errors.lock().unwrap().push(error))
+        .build();
+
+    (Registry::default().with(layer), errors)
+}
+
+/// A subscriber which does not expose the `Registry` the layer was built for.
+struct HiddenRegistry(OtelSubscriber);
+
+impl Subscriber for HiddenRegistry {
+    fn register_callsite(&self, metadata: &'static Metadata<'static>) -> Interest {
+        self.0.register_callsite(metadata)
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        self.0.enabled(metadata)
+    }
+
+    fn new_span(&self, attrs: &span::Attributes<'_>) -> span::Id {
+        self.0.new_span(attrs)
+    }
+
+    fn record(&self, span: &span::Id, values: &span::Record<'_>) {
+        self.0.record(span, values)
+    }
+
+    fn record_follows_from(&self, span: &span::Id, follows: &span::Id) {
+        self.0.record_follows_from(span, follows)
+    }
+
+    fn event(&self, event: &Event<'_>) {
+        self.0.event(event)
+    }
+
+    fn enter(&self, span: &span::Id) {
+        self.0.enter(span)
+    }
+
+    fn exit(&self, span: &span::Id) {
+        self.0.exit(span)
+    }
+
+    fn clone_span(&self, id: &span::Id) -> span::Id {
+        self.0.clone_span(id)
+    }
+
+    fn try_close(&self, id: span::Id) -> bool {
+        self.0.try_close(id)
+    }
+
+    unsafe fn downcast_raw(&self, id: TypeId) -> Option<*const ()> {
+        if id == TypeId::of::<Self>() {
+            Some(self as *const _ as *const ())
+        } else if id == TypeId::of::<Registry>() {
+            None
+        } else {
+            self.0.downcast_raw(id)
+        }
+    }
+}
+
+#[test]
+fn mismatched_subscriber_context() {
+    let (subscriber, errors) = subscriber();
+    tracing::subscriber::with_default(HiddenRegistry(subscriber), || {
+        let span = tracing::info_span!("request");
+        assert_eq!(span.context(), api::SpanContext::empty_context());
+    });
+
+    assert_eq!(*errors.lock().unwrap(), vec![Error::SubscriberMismatch]);
+}
+
+#[test]
+fn mismatched_subscriber_set_parent() {
+    let (subscriber, errors) = subscriber();
+    tracing::subscriber::with_default(HiddenRegistry(subscriber), || {
+        let parent = api::SpanContext::new(
+            api::TraceId::from_u128(1),
+            api::SpanId::from_u64(1),
+            api::TRACE_FLAG_SAMPLED,
+            true,
+        );
+        tracing::info_span!("request").set_parent(parent);
+    });
+
+    assert_eq!(*errors.lock().unwrap(), vec![Error::SubscriberMismatch]);
+}
+
+#[test]
+fn unknown_event_parent() {
+    let (subscriber, errors) = subscriber();
+    let unknown = span::Id::from_u64(42);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!(parent: &unknown, "event");
+    });
+
+    assert_eq!(*errors.lock().unwrap(), vec![Error::SpanNotFound(unknown)]);
+}
+
+#[test]
+fn unknown_follows_from() {
+    let (subscriber, errors) = subscriber();
+    let unknown = span::Id::from_u64(42);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info_span!("request").follows_from(unknown.clone());
+    });
+
+    assert_eq!(*errors.lock().unwrap(), vec![Error::SpanNotFound(unknown)]);
+}
@@ -0,0 +1,37 @@
+mod common;
+
+#[test]
+fn explicit_parents_receive_events() {
+    let spans = common::export(
+        |builder| builder,
+        || {
+            let stored = tracing::info_span!("stored");
+            let current = tracing::info_span!("current");
+            let _guard = current.enter();
+            tracing::info!(parent: &stored, "explicit");
+            tracing::info!("contextual");
+        },
+    );
+
+    let events = |name: &str| {
+        let span = spans.iter().find(|span| span.name == name).unwrap();
+        common::event_names(span)
+    };
+    assert_eq!(events("stored"), vec!["explicit"]);
+    assert_eq!(events("current"), vec!["contextual"]);
+}
+
+#[test]
+fn root_events_not_recorded_in_current_span() {
+    let spans = common::export(
+        |builder| builder,
+        || {
+            let current = tracing::info_span!("current");
+            let _guard = current.enter();
+            tracing::info!(parent: None, "root");
+        },
+    );
+
+    assert_eq!(spans.len(), 1);
+    assert!(spans[0].message_events.is_empty());
+}
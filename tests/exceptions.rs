@@ -0,0 +1,104 @@
+mod common;
+
+use opentelemetry::api;
+use std::error::Error;
+use std::fmt;
+
+/// An error with an optional source.
+#[derive(Debug)]
+struct TestError(&'static str, Option<Box<TestError>>);
+
+impl fmt::Display for TestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl Error for TestError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.1
+            .as_ref()
+            .map(|source| &**source as &(dyn Error + 'static))
+    }
+}
+
+fn string(value: &str) -> Option<api::Value> {
+    Some(api::Value::String(value.to_string()))
+}
+
+#[test]
+fn event_errors_recorded_as_exceptions() {
+    let err = TestError(
+        "request failed",
+        Some(Box::new(TestError(
+            "connection reset",
+            Some(Box::new(TestError("timeout", None))),
+        ))),
+    );
+    let spans = common::export(
+        |builder| builder,
+        || {
+            let span = tracing::info_span!("request");
+            let _guard = span.enter();
+            tracing::warn!(
+                error = &err as &dyn Error,
+                exception.type = "TestError",
+                "failed"
+            );
+        },
+    );
+
+    assert_eq!(spans[0].status_code, api::StatusCode::Unknown);
+    assert_eq!(common::event_names(&spans[0]), vec!["failed", "exception"]);
+    let exception = spans[0].message_events.iter().nth(1).unwrap();
+    assert_eq!(
+        common::event_attribute(exception, "exception.message"),
+        string("request failed")
+    );
+    assert_eq!(
+        common::event_attribute(exception, "exception.stacktrace"),
+        string("connection reset\ntimeout")
+    );
+    assert_eq!(
+        common::event_attribute(exception, "exception.type"),
+        string("TestError")
+    );
+
+    // The type is not recorded as an attribute of the event itself.
+    let event = spans[0].message_events.iter().next().unwrap();
+    assert_eq!(common::event_attribute(event, "exception.type"), None);
+    assert_eq!(
+        common::event_attribute(event, "error"),
+        string("request failed")
+    );
+}
+
+#[test]
+fn span_errors_keep_explicit_status() {
+    let err = TestError("not found", None);
+    let spans = common::export(
+        |builder| builder,
+        || {
+            let span = tracing::info_span!(
+                "request",
+                otel.status_code = "not_found",
+                error = tracing::field::Empty
+            );
+            span.record("error", &err as &dyn Error);
+        },
+    );
+
+    assert_eq!(spans[0].status_code, api::StatusCode::NotFound);
+    assert_eq!(common::event_names(&spans[0]), vec!["exception"]);
+    let exception = spans[0].message_events.iter().next().unwrap();
+    assert_eq!(
+        common::event_attribute(exception, "exception.message"),
+        string("not found")
+    );
+    assert_eq!(
+        common::event_attribute(exception, "exception.stacktrace"),
+        None
+    );
+    assert_eq!(common::event_attribute(exception, "exception.type"), None);
+    assert_eq!(common::attribute(&spans[0], "error"), string("not found"));
+}
@@ -0,0 +1,133 @@
+mod common;
+
+use opentelemetry::api;
+use tracing_opentelemetry::EventRetention;
+
+fn events() {
+    let span = tracing::info_span!("request");
+    let _guard = span.enter();
+    for i in 0..4 {
+        tracing::info!("{}", i);
+    }
+}
+
+#[test]
+fn first_events_retained() {
+    let spans = common::export(
+        |builder| {
+            builder
+                .with_max_events_per_span(2)
+                .with_event_retention(EventRetention::First)
+        },
+        events,
+    );
+
+    assert_eq!(common::event_names(&spans[0]), vec!["0", "1"]);
+    assert_eq!(
+        common::attribute(&spans[0], "otel.dropped_events_count"),
+        Some(api::Value::U64(2))
+    );
+}
+
+#[test]
+fn last_events_retained() {
+    let spans = common::export(
+        |builder| {
+            builder
+                .with_max_events_per_span(2)
+                .with_event_retention(EventRetention::Last)
+        },
+        events,
+    );
+
+    assert_eq!(common::event_names(&spans[0]), vec!["2", "3"]);
+    assert_eq!(
+        common::attribute(&spans[0], "otel.dropped_events_count"),
+        Some(api::Value::U64(2))
+    );
+}
+
+#[test]
+fn no_events_retained() {
+    let spans = common::export(
+        |builder| {
+            builder
+                .with_max_events_per_span(0)
+                .with_event_retention(EventRetention::Last)
+        },
+        events,
+    );
+
+    assert!(spans[0].message_events.is_empty());
+    assert_eq!(
+        common::attribute(&spans[0], "otel.dropped_events_count"),
+        Some(api::Value::U64(4))
+    );
+}
+
+#[test]
+fn attributes_dropped_at_limit() {
+    let spans = common::export(
+        |builder| builder.with_max_attributes_per_span(2),
+        || {
+            tracing::info_span!("request", a = 1, b = 2, c = 3, d = 4);
+        },
+    );
+
+    assert_eq!(common::attribute(&spans[0], "a"), Some(api::Value::I64(1)));
+    assert_eq!(common::attribute(&spans[0], "b"), Some(api::Value::I64(2)));
+    assert_eq!(common::attribute(&spans[0], "c"), None);
+    assert_eq!(
+        common::attribute(&spans[0], "otel.dropped_attributes_count"),
+        Some(api::Value::U64(2))
+    );
+    assert_eq!(
+        common::attribute(&spans[0], "otel.dropped_events_count"),
+        None
+    );
+}
+
+#[test]
+fn attributes_replaced_at_limit() {
+    let spans = common::export(
+        |builder| builder.with_max_attributes_per_span(1),
+        || {
+            let span = tracing::info_span!("request", status = "pending");
+            span.record("status", "done");
+        },
+    );
+
+    assert_eq!(
+        common::attribute(&spans[0], "status"),
+        Some(api::Value::String("done".to_string()))
+    );
+    assert_eq!(
+        common::attribute(&spans[0], "otel.dropped_attributes_count"),
+        None
+    );
+}
+
+#[test]
+fn values_truncated_at_char_boundary() {
+    let spans = common::export(
+        |builder| builder.with_max_attribute_value_length(4),
+        || {
+            let span = tracing::info_span!("request", name = "aäöü");
+            let _guard = span.enter();
+            tracing::info!(city = "Züüri", "event");
+        },
+    );
+
+    // `ä` starts at byte 1 and `ö` at byte 3, which is cut at byte 4.
+    assert_eq!(
+        common::attribute(&spans[0], "name"),
+        Some(api::Value::String("aä".to_string()))
+    );
+    let event = spans[0].message_events.iter().next().unwrap();
+    let city = event
+        .attributes
+        .iter()
+        .find(|attribute| attribute.key == api::Key::new("city"))
+        .unwrap();
+    assert_eq!(city.value, api::Value::String("Zü".to_string()));
+}
@@ -0,0 +1,93 @@
+mod common;
+
+use opentelemetry::exporter::trace::{ExportResult, SpanData, SpanExporter};
+use opentelemetry::{api::Provider, sdk};
+use std::any::Any;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing_opentelemetry::{OpenTelemetryLayer, OrphanEvents};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::Registry;
+
+/// An exporter which logs outside of any span while exporting.
+#[derive(Clone, Debug, Default)]
+struct LoggingExporter(Arc<Mutex<Vec<String>>>);
+
+impl SpanExporter for LoggingExporter {
+    fn export(&self, batch: Vec<Arc<SpanData>>) -> ExportResult {
+        tracing::warn!("exporting {} spans", batch.len());
+        let mut names = self.0.lock().unwrap();
+        names.extend(batch.iter().map(|span| span.name.clone()));
+        ExportResult::Success
+    }
+
+    fn shutdown(&self) {}
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+// The global default dispatcher has no re-entrancy guard, so events emitted
+// by the exporter reach the layer again.
+#[test]
+fn exporter_events_are_not_exported_again() {
+    let exporter = LoggingExporter::default();
+    let provider = sdk::Provider::builder()
+        .with_simple_exporter(exporter.clone())
+        .with_config(sdk::Config {
+            default_sampler: Box::new(sdk::Sampler::Always),
+            ..Default::default()
+        })
+        .build();
+    let layer = OpenTelemetryLayer::builder(provider.get_tracer("test"))
+        .with_orphan_events(OrphanEvents::Span)
+        .build();
+    tracing::subscriber::set_global_default(Registry::default().with(layer)).unwrap();
+
+    tracing::info!("startup failed");
+
+    assert_eq!(*exporter.0.lock().unwrap(), vec!["startup failed"]);
+}
+
+#[test]
+fn thread_span_exported_when_dropped() {
+    let spans = common::export(
+        |builder| builder.with_orphan_events(OrphanEvents::ThreadSpan(Duration::from_secs(3600))),
+        || {
+            tracing::info!("starting");
+            tracing::error!("startup failed");
+        },
+    );
+
+    assert_eq!(spans.len(), 1);
+    assert_eq!(spans[0].name, "orphan_events");
+    assert_eq!(
+        common::event_names(&spans[0]),
+        vec!["starting", "startup failed"]
+    );
+}
+
+#[test]
+fn thread_span_exported_when_flushed() {
+    let exporter = common::TestExporter::default();
+    let layer = OpenTelemetryLayer::builder(common::tracer(exporter.clone()))
+        .with_orphan_events(OrphanEvents::ThreadSpan(Duration::from_secs(3600)))
+        .build();
+    let subscriber = Registry::default().with(layer);
+
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!("starting");
+        assert!(exporter.0.lock().unwrap().is_empty());
+
+        tracing::dispatcher::get_default(|dispatch| {
+            dispatch
+                .downcast_ref::<OpenTelemetryLayer<Registry, sdk::Tracer>>()
+                .unwrap()
+                .flush();
+        });
+        assert_eq!(exporter.0.lock().unwrap().len(), 1);
+    });
+
+    assert_eq!(exporter.0.lock().unwrap().len(), 1);
+}
@@ -0,0 +1,59 @@
+mod common;
+
+use opentelemetry::api;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use tracing_opentelemetry::{OpenTelemetryLayer, PanicHook};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::Registry;
+
+/// A field value which panics when it is recorded.
+struct Bomb;
+
+impl fmt::Debug for Bomb {
+    fn fmt(&self, _f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        panic!("bomb")
+    }
+}
+
+// Both cases share one test, as the global default can only be set once.
+#[test]
+fn panics() {
+    let exporter = common::TestExporter::default();
+    let layer = OpenTelemetryLayer::with_tracer(common::tracer(exporter.clone()));
+    tracing::subscriber::set_global_default(Registry::default().with(layer)).unwrap();
+    PanicHook::new().install();
+
+    // A panic overrides an explicit status.
+    let result = panic::catch_unwind(|| {
+        let span = tracing::info_span!("handler", otel.status_code = "ok");
+        let _guard = span.enter();
+        panic!("handler failed");
+    });
+    assert!(result.is_err());
+    {
+        let spans = exporter.0.lock().unwrap();
+        let span = spans.iter().find(|span| span.name == "handler").unwrap();
+        assert_eq!(span.status_code, api::StatusCode::Unknown);
+        let exception = span.message_events.iter().next().unwrap();
+        assert_eq!(exception.name, "exception");
+    }
+
+    // A panic while the layer records a field must neither deadlock the hook
+    // nor poison the span data.
+    let span = tracing::info_span!("request", status = tracing::field::Empty);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let _guard = span.enter();
+        tracing::info!(field = ?Bomb);
+    }));
+    assert!(result.is_err());
+    span.record("status", "done");
+    drop(span);
+
+    let spans = exporter.0.lock().unwrap();
+    let span = spans.iter().find(|span| span.name == "request").unwrap();
+    assert_eq!(
+        common::attribute(span, "status"),
+        Some(api::Value::String("done".to_string()))
+    );
+}
@@ -0,0 +1,87 @@
+use opentelemetry::{api, sdk};
+use std::sync::{Arc, Mutex};
+use tracing_opentelemetry::{OpenTelemetryLayer, TracerRouter};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::Registry;
+
+/// A provider which keeps the names of the tracers it creates.
+#[derive(Debug)]
+struct RecordingProvider {
+    provider: sdk::Provider,
+    names: Arc<Mutex<Vec<&'static str>>>,
+}
+
+impl api::Provider for RecordingProvider {
+    type Tracer = sdk::Tracer;
+
+    fn get_tracer(&self, name: &'static str) -> Self::Tracer {
+        self.names.lock().unwrap().push(name);
+        self.provider.get_tracer(name)
+    }
+}
+
+// Returns the names of the tracers created while running `f`.
+fn tracer_names<R, F>(configure: R, f: F) -> Vec<&'static str>
+where
+    R: FnOnce(TracerRouter<sdk::Tracer>) -> TracerRouter<sdk::Tracer>,
+    F: FnOnce(),
+{
+    let names = Arc::new(Mutex::new(Vec::new()));
+    let provider = RecordingProvider {
+        provider: sdk::Provider::default(),
+        names: names.clone(),
+    };
+    let layer = OpenTelemetryLayer::routed_builder(configure(TracerRouter::new(provider))).build();
+    tracing::subscriber::with_default(Registry::default().with(layer), f);
+
+    let names = names.lock().unwrap();
+    names.clone()
+}
+
+#[test]
+fn prefixes_match_at_module_boundaries() {
+    let names = tracer_names(
+        |router| router.with_prefix("app::db", "database"),
+        || {
+            tracing::info_span!(target: "app::db", "query");
+            tracing::info_span!(target: "app::db::pool", "checkout");
+            tracing::info_span!(target: "app::dbx", "other");
+        },
+    );
+
+    assert_eq!(names, vec!["database", "database", "app::dbx"]);
+}
+
+#[test]
+fn longest_prefix_wins() {
+    let names = tracer_names(
+        |router| {
+            router
+                .with_prefix("app", "app")
+                .with_prefix("app::db::pool", "pool")
+                .with_prefix("app::db", "database")
+        },
+        || {
+            tracing::info_span!(target: "app::http", "request");
+            tracing::info_span!(target: "app::db::query", "query");
+            tracing::info_span!(target: "app::db::pool", "checkout");
+        },
+    );
+
+    assert_eq!(names, vec!["app", "database", "pool"]);
+}
+
+#[test]
+fn tracers_cached_per_target() {
+    let names = tracer_names(
+        |router| router.with_prefix("app::db", "database"),
+        || {
+            tracing::info_span!(target: "app::db::query", "first");
+            tracing::info_span!(target: "app::db::query", "second");
+            tracing::info_span!(target: "app::db::pool", "checkout");
+        },
+    );
+
+    // Targets sharing a prefix are cached separately.
+    assert_eq!(names, vec!["database", "database"]);
+}
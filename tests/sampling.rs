@@ -0,0 +1,68 @@
+mod common;
+
+use opentelemetry::{api, sdk};
+use std::collections::HashSet;
+use tracing_opentelemetry::{OpenTelemetryLayer, OpenTelemetrySpanExt};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::Registry;
+
+fn remote_context() -> api::SpanContext {
+    api::SpanContext::new(
+        api::TraceId::from_u128(42),
+        api::SpanId::from_u64(42),
+        api::TRACE_FLAG_SAMPLED,
+        true,
+    )
+}
+
+// Returns the ids of the spans whose propagated context claims they are
+// sampled, and the ids of the exported spans.
+fn sample(sampler: sdk::Sampler, f: impl Fn() -> tracing::Span) -> (HashSet<u64>, HashSet<u64>) {
+    let exporter = common::TestExporter::default();
+    let layer =
+        OpenTelemetryLayer::builder(common::sampled_tracer(exporter.clone(), sampler)).build();
+
+    let mut sampled = HashSet::new();
+    tracing::subscriber::with_default(Registry::default().with(layer), || {
+        for _ in 0..100 {
+            let span = f();
+            let child = tracing::info_span!(parent: &span, "child");
+            for span in &[span, child] {
+                let context = span.context();
+                if context.is_sampled() {
+                    sampled.insert(context.span_id().to_u64());
+                }
+            }
+        }
+    });
+
+    let exported = exporter
+        .0
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|span| span.context.span_id().to_u64())
+        .collect();
+    (sampled, exported)
+}
+
+#[test]
+fn remote_parent_sampled_once() {
+    let (sampled, exported) = sample(sdk::Sampler::Probability(0.5), || {
+        let span = tracing::info_span!("request");
+        span.set_parent(remote_context());
+        span
+    });
+
+    assert!(!sampled.is_empty());
+    assert!(sampled.len() < 200);
+    assert_eq!(sampled, exported);
+}
+
+#[test]
+fn root_decision_propagated() {
+    let (sampled, exported) = sample(sdk::Sampler::Never, || tracing::info_span!("request"));
+
+    assert!(sampled.is_empty());
+    assert!(exported.is_empty());
+}
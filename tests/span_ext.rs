@@ -0,0 +1,85 @@
+mod common;
+
+use opentelemetry::api;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+#[test]
+fn attributes_set_on_span() {
+    let spans = common::export(
+        |builder| builder,
+        || {
+            let span = tracing::info_span!("request");
+            span.set_attribute(api::Key::new("http.status_code").i64(200));
+            span.set_attributes(vec![
+                api::Key::new(format!("http.request.header.{}", "accept")).string("*/*"),
+                api::Key::new("http.status_code").i64(404),
+            ]);
+        },
+    );
+
+    assert_eq!(
+        common::attribute(&spans[0], "http.request.header.accept"),
+        Some(api::Value::String("*/*".to_string()))
+    );
+    assert_eq!(
+        common::attribute(&spans[0], "http.status_code"),
+        Some(api::Value::I64(404))
+    );
+}
+
+#[test]
+fn reserved_attributes_configure_span() {
+    let spans = common::export(
+        |builder| builder,
+        || {
+            let span = tracing::info_span!("request");
+            span.set_attributes(vec![
+                api::Key::new("otel.name").string("GET /users"),
+                api::Key::new("otel.kind").string("server"),
+                api::Key::new("otel.status_code").u64(5),
+                api::Key::new("otel.status_message").string("no such user"),
+            ]);
+        },
+    );
+
+    assert_eq!(spans[0].name, "GET /users");
+    assert_eq!(spans[0].span_kind, api::SpanKind::Server);
+    assert_eq!(spans[0].status_code, api::StatusCode::NotFound);
+    assert_eq!(spans[0].status_message, "no such user");
+    assert!(spans[0].attributes.is_empty());
+}
+
+#[test]
+fn links_added_to_span() {
+    let remote = |id| {
+        api::SpanContext::new(
+            api::TraceId::from_u128(id as u128),
+            api::SpanId::from_u64(id),
+            api::TRACE_FLAG_SAMPLED,
+            true,
+        )
+    };
+    let spans = common::export(
+        |builder| builder,
+        || {
+            let span = tracing::info_span!("batch");
+            span.add_link(remote(1));
+            span.add_link_with_attributes(
+                remote(2),
+                vec![api::Key::new("messaging.message_id").string("2")],
+            );
+            // Invalid contexts are ignored.
+            span.add_link(api::SpanContext::empty_context());
+        },
+    );
+
+    let links: Vec<&api::Link> = spans[0].links.iter().collect();
+    assert_eq!(links.len(), 2);
+    assert_eq!(links[0].span_context().span_id(), api::SpanId::from_u64(1));
+    assert!(links[0].attributes().is_empty());
+    assert_eq!(links[1].span_context().span_id(), api::SpanId::from_u64(2));
+    assert_eq!(
+        links[1].attributes(),
+        &vec![api::Key::new("messaging.message_id").string("2")]
+    );
+}
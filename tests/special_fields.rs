@@ -0,0 +1,86 @@
+mod common;
+
+use opentelemetry::api;
+use tracing_attributes::instrument;
+
+#[instrument(fields(otel.kind = "client"))]
+fn request() {}
+
+#[test]
+fn unknown_status_codes_keep_status() {
+    let spans = common::export(
+        |builder| builder,
+        || {
+            let span = tracing::info_span!("request", otel.status_code = "not_found");
+            span.record("otel.status_code", "bogus");
+            span.record("otel.status_code", 500u64);
+        },
+    );
+
+    assert_eq!(spans[0].status_code, api::StatusCode::NotFound);
+    assert!(spans[0].attributes.is_empty());
+}
+
+#[test]
+fn reserved_fields_are_not_attributes() {
+    let spans = common::export(
+        |builder| builder,
+        || {
+            tracing::info_span!("request", otel.status_code = -1i64, otel.kind = true);
+        },
+    );
+
+    assert_eq!(spans[0].status_code, api::StatusCode::OK);
+    assert!(spans[0].attributes.is_empty());
+}
+
+#[test]
+fn span_kinds_mapped() {
+    let spans = common::export(
+        |builder| builder,
+        || {
+            tracing::info_span!("server", otel.kind = "server");
+            tracing::info_span!("producer", otel.kind = "Producer");
+            request();
+        },
+    );
+
+    let kind = |name: &str| {
+        spans
+            .iter()
+            .find(|span| span.name == name)
+            .map(|span| span.span_kind.clone())
+    };
+    assert_eq!(kind("server"), Some(api::SpanKind::Server));
+    assert_eq!(kind("producer"), Some(api::SpanKind::Producer));
+    assert_eq!(kind("request"), Some(api::SpanKind::Client));
+    assert!(spans.iter().all(|span| span.attributes.is_empty()));
+}
+
+#[test]
+fn unknown_span_kinds_keep_kind() {
+    let spans = common::export(
+        |builder| builder,
+        || {
+            let span = tracing::info_span!("request", otel.kind = "server");
+            span.record("otel.kind", "bogus");
+        },
+    );
+
+    assert_eq!(spans[0].span_kind, api::SpanKind::Server);
+    assert!(spans[0].attributes.is_empty());
+}
+
+#[test]
+fn span_renamed_on_record() {
+    let spans = common::export(
+        |builder| builder,
+        || {
+            let span = tracing::info_span!("request", otel.name = "HTTP request");
+            span.record("otel.name", "GET /users/{id}");
+        },
+    );
+
+    assert_eq!(spans[0].name, "GET /users/{id}");
+    assert!(spans[0].attributes.is_empty());
+}
@@ -0,0 +1,44 @@
+mod common;
+
+use opentelemetry::api;
+use std::thread;
+use std::time::Duration;
+
+fn nanos(value: Option<api::Value>) -> u64 {
+    match value {
+        Some(api::Value::U64(nanos)) => nanos,
+        value => panic!("expected nanoseconds, got {:?}", value),
+    }
+}
+
+#[test]
+fn busy_and_idle_time_tracked() {
+    let spans = common::export(
+        |builder| builder.with_tracked_inactivity(true),
+        || {
+            let span = tracing::info_span!("task");
+            thread::sleep(Duration::from_millis(20));
+            span.in_scope(|| thread::sleep(Duration::from_millis(10)));
+            thread::sleep(Duration::from_millis(20));
+        },
+    );
+
+    let busy = nanos(common::attribute(&spans[0], "busy_ns"));
+    let idle = nanos(common::attribute(&spans[0], "idle_ns"));
+    assert!(busy >= 10_000_000, "busy_ns = {}", busy);
+    assert!(idle >= 40_000_000, "idle_ns = {}", idle);
+}
+
+#[test]
+fn inactivity_not_tracked_by_default() {
+    let spans = common::export(
+        |builder| builder,
+        || {
+            let span = tracing::info_span!("task");
+            span.in_scope(|| {});
+        },
+    );
+
+    assert_eq!(common::attribute(&spans[0], "busy_ns"), None);
+    assert_eq!(common::attribute(&spans[0], "idle_ns"), None);
+}